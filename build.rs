@@ -0,0 +1,69 @@
+//! Determines the exact version of naga that hotglsl is compiled against, so that a `DiskCache`
+//! is invalidated by any naga upgrade, including patch releases.
+
+use std::env;
+use std::fs;
+use std::path::{Path, PathBuf};
+
+fn main() {
+    let lock = env::var_os("OUT_DIR")
+        .and_then(|dir| find_lock_file(Path::new(&dir)))
+        .or_else(|| {
+            let dir = env::var_os("CARGO_MANIFEST_DIR")?;
+            find_lock_file(Path::new(&dir))
+        });
+    let versions = match lock {
+        Some(ref lock) => {
+            println!("cargo:rerun-if-changed={}", lock.display());
+            naga_versions(lock)
+        }
+        None => vec![],
+    };
+    // Fall back to the version requirement if the lock file could not be found, in which case a
+    // `DiskCache` is only invalidated by naga upgrades that change the requirement.
+    let version = if versions.is_empty() {
+        println!(
+            "cargo:warning=could not find the version of naga within a Cargo.lock, so a \
+             `DiskCache` will not be invalidated by naga patch releases"
+        );
+        "0.14".to_string()
+    } else {
+        versions.join(",")
+    };
+    println!("cargo:rustc-env=HOTGLSL_NAGA_VERSION={}", version);
+    println!("cargo:rerun-if-changed=build.rs");
+}
+
+/// The nearest `Cargo.lock` within the given directory or any of its ancestors.
+///
+/// Cargo does not tell build scripts where the lock file of the workspace being built is, so this
+/// is a heuristic. `OUT_DIR` is usually within the workspace's target directory, but a shared
+/// `CARGO_TARGET_DIR` may lead to the lock file of an unrelated workspace, or to none at all, in
+/// which case the crate's own directory is searched instead.
+fn find_lock_file(dir: &Path) -> Option<PathBuf> {
+    dir.ancestors()
+        .map(|dir| dir.join("Cargo.lock"))
+        .find(|lock| lock.is_file())
+}
+
+/// The version of every `naga` package within the given lock file.
+///
+/// All are returned, as a lock file may contain multiple versions of naga.
+fn naga_versions(lock: &Path) -> Vec<String> {
+    let contents = fs::read_to_string(lock).unwrap_or_default();
+    let mut versions = vec![];
+    let mut lines = contents.lines().map(str::trim);
+    while let Some(line) = lines.next() {
+        if line != "name = \"naga\"" {
+            continue;
+        }
+        let version = lines
+            .next()
+            .and_then(|line| line.strip_prefix("version = \""))
+            .and_then(|rest| rest.strip_suffix('"'));
+        if let Some(version) = version {
+            versions.push(version.to_string());
+        }
+    }
+    versions
+}
@@ -0,0 +1,27 @@
+use futures::StreamExt;
+
+fn main() {
+    let shader_dir = std::path::Path::new(env!("CARGO_MANIFEST_DIR"))
+        .join("examples")
+        .join("shaders");
+    let mut watch = hotglsl::watch(&shader_dir).unwrap();
+    watch.set_debounce_window(std::time::Duration::from_millis(10));
+    println!("Edit the shaders in `examples/shaders/`!");
+
+    // Any executor will do - the stream is woken directly by the file system watcher.
+    futures::executor::block_on(async {
+        let mut shaders = watch.into_compile_stream();
+        while let Some((path, result)) = shaders.next().await {
+            println!("Tried compiling {:?}:", path);
+            match result {
+                Ok(_spirv_bytes) => println!("  Success!"),
+                Err(e) => {
+                    println!("  Woopsie!");
+                    for diagnostic in e.diagnostics() {
+                        println!("{}", diagnostic);
+                    }
+                }
+            }
+        }
+    });
+}
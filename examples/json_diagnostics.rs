@@ -0,0 +1,19 @@
+//! Streams the result of each hot recompile to stdout as a line of JSON, suitable for consumption
+//! by an editor.
+//!
+//! Run with `cargo run --example json_diagnostics --features serde`.
+
+fn main() {
+    let shader_dir = std::path::Path::new(env!("CARGO_MANIFEST_DIR"))
+        .join("examples")
+        .join("shaders");
+    let mut watch = hotglsl::watch(&shader_dir).unwrap();
+    watch.set_debounce_window(std::time::Duration::from_millis(10));
+    loop {
+        watch.await_event().unwrap();
+        for (path, result) in watch.compile_touched().unwrap() {
+            let report = hotglsl::CompileReport::new(&path, &result);
+            println!("{}", serde_json::to_string(&report).unwrap());
+        }
+    }
+}
@@ -0,0 +1,128 @@
+//! The notify backends that may be used to receive file system events.
+//!
+//! See `WatchBuilder::backend`.
+
+use notify::Watcher;
+use std::path::{Path, PathBuf};
+use std::time::Duration;
+
+/// The interval at which the watched paths are polled when falling back from the native backend.
+pub const DEFAULT_POLL_INTERVAL: Duration = Duration::from_secs(1);
+
+/// The notify backend used to receive file system events.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum Backend {
+    /// The platform's native backend, e.g. inotify on Linux.
+    #[default]
+    Native,
+    /// notify's `PollWatcher`, which re-scans the watched paths at the given interval.
+    ///
+    /// This works where native events do not fire, e.g. on network drives or within some
+    /// container bind mounts, at the cost of scanning every watched file at each interval.
+    ///
+    /// Modifications are detected via modification times, which some file systems only record to
+    /// the second. If `compare_contents` is `true`, the contents of every watched file are also
+    /// hashed at each interval, so that repeated saves within the same second are detected.
+    Poll {
+        interval: Duration,
+        compare_contents: bool,
+    },
+}
+
+/// A notify watcher of any backend.
+pub(crate) type AnyWatcher = Box<dyn Watcher + Send + Sync>;
+
+/// Create a watcher of the given backend.
+pub(crate) fn watcher<F>(backend: Backend, handler: F) -> notify::Result<AnyWatcher>
+where
+    F: notify::EventHandler,
+{
+    let watcher: AnyWatcher = match backend {
+        Backend::Native => Box::new(notify::recommended_watcher(handler)?),
+        Backend::Poll {
+            interval,
+            compare_contents,
+        } => {
+            let config = notify::Config::default()
+                .with_poll_interval(interval)
+                .with_compare_contents(compare_contents);
+            Box::new(notify::PollWatcher::new(handler, config)?)
+        }
+    };
+    Ok(watcher)
+}
+
+/// Watch the given path, recursively if it is a directory and `recursive` is `true`.
+pub(crate) fn watch_path(
+    watcher: &mut dyn Watcher,
+    path: &Path,
+    recursive: bool,
+) -> notify::Result<()> {
+    let mode = if recursive && path.is_dir() {
+        notify::RecursiveMode::Recursive
+    } else {
+        notify::RecursiveMode::NonRecursive
+    };
+    watcher.watch(path, mode)
+}
+
+/// Stop watching the given path.
+///
+/// Succeeds if the path was not being watched or no longer exists, e.g. if its directory has since
+/// been removed, in which case the backend has already dropped the watch.
+pub(crate) fn unwatch_path(watcher: &mut dyn Watcher, path: &Path) -> notify::Result<()> {
+    match watcher.unwatch(path) {
+        Err(err) if is_missing(&err) => Ok(()),
+        result => result,
+    }
+}
+
+/// Stop watching the given path unless it lies within one of the given watched paths or include
+/// directories.
+///
+/// Unwatching a directory also unwatches those beneath it, so any of the watched paths and include
+/// directories nested beneath it are watched again.
+pub(crate) fn unwatch_within(
+    watcher: &mut dyn Watcher,
+    path: &Path,
+    watched_paths: &[PathBuf],
+    recursive: bool,
+    include_dirs: &[PathBuf],
+) -> notify::Result<()> {
+    let mut still_watched = watched_paths.iter().chain(include_dirs);
+    if still_watched.any(|p| path.starts_with(p)) {
+        return Ok(());
+    }
+    unwatch_path(watcher, path)?;
+    for p in watched_paths.iter().filter(|p| p.starts_with(path)) {
+        watch_path(watcher, p, recursive)?;
+    }
+    for dir in include_dirs.iter().filter(|d| d.starts_with(path)) {
+        watcher.watch(dir, notify::RecursiveMode::Recursive)?;
+    }
+    Ok(())
+}
+
+/// Whether or not the error indicates that a path or its watch does not exist.
+fn is_missing(err: &notify::Error) -> bool {
+    match err.kind {
+        notify::ErrorKind::PathNotFound | notify::ErrorKind::WatchNotFound => true,
+        notify::ErrorKind::Io(ref err) => err.kind() == std::io::ErrorKind::NotFound,
+        _ => false,
+    }
+}
+
+/// Whether or not the error indicates a failure of the backend itself, in which case falling back
+/// to polling may help, rather than a problem with the given paths or configuration.
+pub(crate) fn is_backend_error(err: &notify::Error) -> bool {
+    match err.kind {
+        notify::ErrorKind::PathNotFound
+        | notify::ErrorKind::WatchNotFound
+        | notify::ErrorKind::InvalidConfig(_) => false,
+        notify::ErrorKind::Io(ref err) => !matches!(
+            err.kind(),
+            std::io::ErrorKind::NotFound | std::io::ErrorKind::PermissionDenied
+        ),
+        notify::ErrorKind::Generic(_) | notify::ErrorKind::MaxFilesWatch => true,
+    }
+}
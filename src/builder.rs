@@ -0,0 +1,227 @@
+//! A builder for configuring a `Watch`.
+//!
+//! See `WatchBuilder`.
+
+use crate::backend::{self, Backend, DEFAULT_POLL_INTERVAL};
+use crate::{cache, debounce, include, CompileOptions};
+use crate::{CreationError, EventState, FileFilter, Watch, DEFAULT_CACHE_CAPACITY};
+use std::collections::HashMap;
+use std::path::{Path, PathBuf};
+use std::sync::{mpsc, Arc, Mutex};
+use std::time::Duration;
+
+/// Configures and creates a `Watch`.
+///
+/// The `watch` and `watch_paths` functions are shortcuts for a builder with default settings.
+#[derive(Clone, Debug)]
+pub struct WatchBuilder {
+    paths: Vec<PathBuf>,
+    compile_options: CompileOptions,
+    backend: Backend,
+    poll_fallback: Option<Duration>,
+    max_depth: Option<usize>,
+    follow_symlinks: bool,
+    file_filter: FileFilter,
+    debounce_window: Duration,
+    initial_scan: bool,
+}
+
+impl WatchBuilder {
+    /// A builder watching no paths with the native backend and default compile options.
+    ///
+    /// Directories are watched to any depth, symlinks are followed, every file with the extension
+    /// of a supported language is a shader and touched shaders are not debounced.
+    pub fn new() -> Self {
+        WatchBuilder {
+            paths: vec![],
+            compile_options: CompileOptions::default(),
+            backend: Backend::default(),
+            poll_fallback: Some(DEFAULT_POLL_INTERVAL),
+            max_depth: None,
+            follow_symlinks: true,
+            file_filter: FileFilter::default(),
+            debounce_window: Duration::from_secs(0),
+            initial_scan: false,
+        }
+    }
+
+    /// Watch the given file or directory of files.
+    pub fn path<P>(mut self, path: P) -> Self
+    where
+        P: AsRef<Path>,
+    {
+        self.paths.push(path.as_ref().to_path_buf());
+        self
+    }
+
+    /// Watch each of the given files or directories of files.
+    pub fn paths<I>(mut self, paths: I) -> Self
+    where
+        I: IntoIterator,
+        I::Item: AsRef<Path>,
+    {
+        self.paths
+            .extend(paths.into_iter().map(|p| p.as_ref().to_path_buf()));
+        self
+    }
+
+    /// The options with which touched shaders are compiled.
+    ///
+    /// The options' include directories are watched too, so that touching a shared header yields
+    /// every shader that transitively includes it.
+    pub fn compile_options(mut self, options: CompileOptions) -> Self {
+        self.compile_options = options;
+        self
+    }
+
+    /// The notify backend used to receive file system events. `Backend::Native` by default.
+    pub fn backend(mut self, backend: Backend) -> Self {
+        self.backend = backend;
+        self
+    }
+
+    /// The interval at which to poll the watched paths if the native backend fails, or `None` to
+    /// return the error instead.
+    ///
+    /// By default, falls back to polling every `DEFAULT_POLL_INTERVAL` without comparing contents.
+    /// The backend in use may be checked via `Watch::backend`. Errors caused by the paths
+    /// themselves, e.g. a path that does not exist, are always returned.
+    pub fn poll_fallback(mut self, interval: Option<Duration>) -> Self {
+        self.poll_fallback = interval;
+        self
+    }
+
+    /// The maximum number of directories beneath each watched directory in which shaders are
+    /// watched, or `None` for no limit.
+    ///
+    /// `Some(0)` only watches the shaders directly within each watched directory.
+    pub fn max_depth(mut self, depth: Option<usize>) -> Self {
+        self.max_depth = depth;
+        self
+    }
+
+    /// Whether or not to watch shaders within symlinked directories, as well as symlinked shaders.
+    /// `true` by default.
+    ///
+    /// The watched paths themselves are always followed.
+    pub fn follow_symlinks(mut self, follow: bool) -> Self {
+        self.follow_symlinks = follow;
+        self
+    }
+
+    /// The filter determining which files beneath the watched paths are treated as shaders.
+    ///
+    /// See `Watch::set_file_filter`.
+    pub fn file_filter(mut self, filter: FileFilter) -> Self {
+        self.file_filter = filter;
+        self
+    }
+
+    /// The duration for which touched shaders must go untouched before being yielded.
+    ///
+    /// See `Watch::set_debounce_window`.
+    pub fn debounce_window(mut self, window: Duration) -> Self {
+        self.debounce_window = window;
+        self
+    }
+
+    /// Whether or not to mark every existing shader beneath the watched paths as touched upon
+    /// creation, so that the first `compile_touched` compiles all of them. `false` by default.
+    ///
+    /// See `Watch::touch_all`.
+    pub fn initial_scan(mut self, scan: bool) -> Self {
+        self.initial_scan = scan;
+        self
+    }
+
+    /// Create the `Watch`.
+    pub fn build(self) -> Result<Watch, CreationError> {
+        // Channel for sending events back to the main thread.
+        let (tx, event_rx) = mpsc::channel();
+        #[cfg(feature = "async")]
+        let wakers = Arc::new(crate::stream::EventWakers::default());
+        let handler = || {
+            let tx = tx.clone();
+            #[cfg(feature = "async")]
+            let wakers = wakers.clone();
+            move |res| {
+                tx.send(res).ok();
+                #[cfg(feature = "async")]
+                wakers.wake();
+            }
+        };
+
+        // Create a watcher for all paths, falling back to polling if the native backend fails.
+        let options = self.compile_options;
+        let watched_paths = self.paths;
+        let recursive = self.max_depth != Some(0);
+        let create_watcher = |backend| -> notify::Result<_> {
+            let mut watcher = backend::watcher(backend, handler())?;
+            for path in &watched_paths {
+                backend::watch_path(&mut *watcher, path, recursive)?;
+            }
+            for dir in &options.include_dirs {
+                watcher.watch(dir, notify::RecursiveMode::Recursive)?;
+            }
+            Ok(watcher)
+        };
+        let mut backend = self.backend;
+        let watcher = match create_watcher(backend) {
+            Ok(watcher) => watcher,
+            Err(err) => match self.poll_fallback {
+                Some(interval) if backend == Backend::Native && backend::is_backend_error(&err) => {
+                    let compare_contents = false;
+                    backend = Backend::Poll {
+                        interval,
+                        compare_contents,
+                    };
+                    create_watcher(backend)?
+                }
+                _ => return Err(err.into()),
+            },
+        };
+
+        let mut debouncer = debounce::Debouncer::new();
+        debouncer.set_window(self.debounce_window);
+        let events = Mutex::new(EventState {
+            pending_paths: vec![],
+            shader_events: vec![],
+            debouncer,
+            dependencies: include::DependencyGraph::default(),
+        });
+        let mut watch = Watch {
+            event_rx: Mutex::new(event_rx),
+            events,
+            compile_options: options,
+            file_filter: self.file_filter,
+            ignore_cache: Default::default(),
+            permutations: HashMap::new(),
+            programs: vec![],
+            #[cfg(feature = "async")]
+            wakers,
+            cache: Arc::new(cache::CompileCache::new(DEFAULT_CACHE_CAPACITY)),
+            last_good: Default::default(),
+            interfaces: Default::default(),
+            workers: None,
+            backend,
+            watcher,
+            watched_paths,
+            max_depth: self.max_depth,
+            follow_symlinks: self.follow_symlinks,
+        };
+
+        // Find the includes of all existing shaders so that we know what to recompile when they
+        // change.
+        watch.rescan_dependencies();
+        if self.initial_scan {
+            watch.touch_all();
+        }
+        Ok(watch)
+    }
+}
+
+impl Default for WatchBuilder {
+    fn default() -> Self {
+        Self::new()
+    }
+}
@@ -0,0 +1,664 @@
+//! Caching of compiled shaders keyed by a hash of their content and compile options.
+//!
+//! See `Watch::cache_stats` and `DiskCache`.
+
+use crate::{
+    compile_expanded, compile_file, preprocess_and_resolve, CompileError, CompileOptions,
+    CompiledShader, Preprocessed, Reflection, ShaderStage, SourceLanguage,
+};
+use std::collections::{HashMap, VecDeque};
+use std::hash::{Hash, Hasher};
+use std::io::Write;
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::Mutex;
+use std::{fs, io};
+
+/// The number of compiled shaders retained by a `Watch`'s cache by default.
+pub const DEFAULT_CACHE_CAPACITY: usize = 256;
+
+/// The total size of the files retained by a `DiskCache` by default.
+pub const DEFAULT_DISK_CACHE_SIZE: u64 = 64 * 1024 * 1024;
+
+/// The exact version of naga used for compilation, as determined from `Cargo.lock` by the build
+/// script. Included within each cache key so that upgrading naga invalidates cached output.
+const NAGA_VERSION: &str = env!("HOTGLSL_NAGA_VERSION");
+
+/// The extension of cached artifacts within a `DiskCache` directory.
+const DISK_CACHE_EXT: &str = "bin";
+
+/// The length of the checksum preceding the compiled bytes within each artifact.
+const CHECKSUM_LEN: usize = 16;
+
+/// Distinguishes the temporary files of concurrent stores within the same process.
+static TMP_COUNTER: AtomicU64 = AtomicU64::new(0);
+
+/// Counters describing the effectiveness of a compile cache.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Hash)]
+pub struct CacheStats {
+    /// The number of compiles served from the cache.
+    pub hits: u64,
+    /// The number of compiles that invoked naga.
+    pub misses: u64,
+    /// The number of compiles served from the `DiskCache` of the `CompileOptions`, if any.
+    pub disk_hits: u64,
+    /// The number of compiled shaders currently retained in memory.
+    pub entries: usize,
+}
+
+/// An in-memory cache of successfully compiled shaders.
+///
+/// Entries are evicted in the order in which they were inserted once `capacity` is exceeded.
+#[derive(Debug)]
+pub(crate) struct CompileCache {
+    capacity: usize,
+    entries: Mutex<Entries>,
+    hits: AtomicU64,
+    disk_hits: AtomicU64,
+    misses: AtomicU64,
+}
+
+/// A directory of compiled shaders that persists between runs of an application.
+///
+/// Attach to `CompileOptions` via `CompileOptions::disk_cache`, after which `compile_with_options`
+/// and a `Watch`'s hot recompiles read and write the cache. Artifacts are keyed by a hash of the
+/// shader's source with all includes expanded, its stage, the `CompileOptions` and the versions of
+/// naga and hotglsl, so any change to these invalidates the artifact.
+///
+/// Whenever an artifact is stored and the total size of the cached artifacts exceeds the size
+/// cap, the least recently used artifacts are removed. Only the compiled bytes are stored, so
+/// functions producing reflection such as `compile_reflected` bypass the cache.
+///
+/// Each artifact is prefixed with a checksum of its bytes. Artifacts that fail the checksum, e.g.
+/// due to a crash mid-write, are removed and recompiled.
+#[derive(Clone, Debug, PartialEq, Eq, Hash)]
+pub struct DiskCache {
+    dir: PathBuf,
+    max_size: u64,
+}
+
+/// The cache key of a shader along with the source from which the key was hashed.
+///
+/// The same source is compiled upon a miss, so that an edit made between hashing and compiling
+/// cannot cause output to be cached under the key of other content.
+struct Keyed {
+    key: u128,
+    source: KeyedSource,
+}
+
+enum KeyedSource {
+    /// GLSL with all includes expanded along with its resolved stage.
+    Glsl(Preprocessed, ShaderStage),
+    #[cfg(feature = "wgsl-in")]
+    Wgsl(String),
+    #[cfg(feature = "spv-in")]
+    Spirv(Vec<u8>),
+}
+
+#[derive(Debug, Default)]
+struct Entries {
+    shaders: HashMap<u128, CompiledShader>,
+    order: VecDeque<u128>,
+}
+
+/// A 128-bit FNV-1a hasher.
+///
+/// Unlike the std `DefaultHasher`, the output is stable between runs, allowing hashes to be used
+/// as persistent keys.
+#[derive(Clone, Debug)]
+pub(crate) struct ContentHasher(u128);
+
+impl CompileCache {
+    /// A cache retaining at most `capacity` compiled shaders. A capacity of zero disables caching.
+    pub(crate) fn new(capacity: usize) -> Self {
+        CompileCache {
+            capacity,
+            entries: Mutex::new(Entries::default()),
+            hits: AtomicU64::new(0),
+            disk_hits: AtomicU64::new(0),
+            misses: AtomicU64::new(0),
+        }
+    }
+
+    pub(crate) fn stats(&self) -> CacheStats {
+        CacheStats {
+            hits: self.hits.load(Ordering::Relaxed),
+            disk_hits: self.disk_hits.load(Ordering::Relaxed),
+            misses: self.misses.load(Ordering::Relaxed),
+            entries: self.entries.lock().unwrap().shaders.len(),
+        }
+    }
+
+    /// Remove all entries, leaving the counters untouched.
+    pub(crate) fn clear(&self) {
+        let mut entries = self.entries.lock().unwrap();
+        entries.shaders.clear();
+        entries.order.clear();
+    }
+
+    /// Compile the shader at the given path, or return the previously compiled shader if neither
+    /// its content nor the options have changed since.
+    ///
+    /// For GLSL, the content is the source with all includes expanded, so changes to included
+    /// files are detected. Failed compiles are not cached.
+    pub(crate) fn compile(
+        &self,
+        path: &Path,
+        options: &CompileOptions,
+    ) -> Result<CompiledShader, CompileError> {
+        if self.capacity == 0 {
+            return compile_file(path, options, &*options.stage_resolver);
+        }
+        let keyed = Keyed::new(path, options)?;
+        if let Some(shader) = self.get(keyed.key) {
+            return Ok(shader);
+        }
+        self.misses.fetch_add(1, Ordering::Relaxed);
+        let key = keyed.key;
+        let shader = keyed.compile(path, options)?;
+        self.insert(key, shader.clone());
+        if let Some(ref disk) = options.disk_cache {
+            disk.store(key, &shader.bytes);
+        }
+        Ok(shader)
+    }
+
+    /// Compile the shader at the given path to bytes, consulting the `DiskCache` of the options
+    /// upon missing the in-memory cache.
+    pub(crate) fn compile_bytes(
+        &self,
+        path: &Path,
+        options: &CompileOptions,
+    ) -> Result<Vec<u8>, CompileError> {
+        let disk = match options.disk_cache {
+            Some(ref disk) => disk,
+            None => return self.compile(path, options).map(|shader| shader.bytes),
+        };
+        let keyed = Keyed::new(path, options)?;
+        if let Some(shader) = self.get(keyed.key) {
+            return Ok(shader.bytes);
+        }
+        if let Some(bytes) = disk.load(keyed.key) {
+            self.disk_hits.fetch_add(1, Ordering::Relaxed);
+            return Ok(bytes);
+        }
+        self.misses.fetch_add(1, Ordering::Relaxed);
+        let key = keyed.key;
+        let shader = keyed.compile(path, options)?;
+        disk.store(key, &shader.bytes);
+        if self.capacity > 0 {
+            self.insert(key, shader.clone());
+        }
+        Ok(shader.bytes)
+    }
+
+    /// Reflect the shader at the given path, reusing its cached compile if any.
+    ///
+    /// Unlike `compile`, the counters are left untouched and a newly compiled shader is not
+    /// cached, so that a shader may be inspected on behalf of another, e.g. to check the interfaces
+    /// of a program.
+    pub(crate) fn reflect(
+        &self,
+        path: &Path,
+        options: &CompileOptions,
+    ) -> Result<Reflection, CompileError> {
+        let keyed = Keyed::new(path, options)?;
+        let entries = self.entries.lock().unwrap();
+        if let Some(shader) = entries.shaders.get(&keyed.key) {
+            return Ok(shader.reflection.clone());
+        }
+        drop(entries);
+        keyed.compile(path, options).map(|shader| shader.reflection)
+    }
+
+    /// Look up the shader with the given key, counting a hit if found.
+    fn get(&self, key: u128) -> Option<CompiledShader> {
+        if self.capacity == 0 {
+            return None;
+        }
+        let shader = self.entries.lock().unwrap().shaders.get(&key).cloned()?;
+        self.hits.fetch_add(1, Ordering::Relaxed);
+        Some(shader)
+    }
+
+    fn insert(&self, key: u128, shader: CompiledShader) {
+        let mut entries = self.entries.lock().unwrap();
+        if entries.shaders.insert(key, shader).is_none() {
+            entries.order.push_back(key);
+        }
+        while entries.order.len() > self.capacity {
+            if let Some(oldest) = entries.order.pop_front() {
+                entries.shaders.remove(&oldest);
+            }
+        }
+    }
+}
+
+impl DiskCache {
+    /// A cache within the given directory, which is created if it does not yet exist.
+    pub fn new<P>(dir: P) -> io::Result<Self>
+    where
+        P: AsRef<Path>,
+    {
+        let dir = dir.as_ref().to_path_buf();
+        fs::create_dir_all(&dir)?;
+        Ok(DiskCache {
+            dir,
+            max_size: DEFAULT_DISK_CACHE_SIZE,
+        })
+    }
+
+    /// The maximum total size in bytes of all cached artifacts.
+    ///
+    /// Defaults to `DEFAULT_DISK_CACHE_SIZE`.
+    pub fn max_size(mut self, bytes: u64) -> Self {
+        self.max_size = bytes;
+        self
+    }
+
+    /// The directory in which artifacts are cached.
+    pub fn dir(&self) -> &Path {
+        &self.dir
+    }
+
+    /// The total size in bytes of all cached artifacts.
+    pub fn size(&self) -> io::Result<u64> {
+        let size = self.artifacts()?.iter().map(|(_, meta)| meta.len()).sum();
+        Ok(size)
+    }
+
+    /// Remove all cached artifacts.
+    pub fn clear(&self) -> io::Result<()> {
+        for (path, _) in self.artifacts()? {
+            fs::remove_file(path)?;
+        }
+        Ok(())
+    }
+
+    /// Compile the shader at the given path, or load the previously compiled artifact.
+    pub(crate) fn compile(
+        &self,
+        path: &Path,
+        options: &CompileOptions,
+    ) -> Result<Vec<u8>, CompileError> {
+        let keyed = Keyed::new(path, options)?;
+        if let Some(bytes) = self.load(keyed.key) {
+            return Ok(bytes);
+        }
+        let key = keyed.key;
+        let shader = keyed.compile(path, options)?;
+        self.store(key, &shader.bytes);
+        Ok(shader.bytes)
+    }
+
+    fn artifact_path(&self, key: u128) -> PathBuf {
+        self.dir.join(format!("{:032x}.{}", key, DISK_CACHE_EXT))
+    }
+
+    /// Load the artifact with the given key, marking it as recently used.
+    ///
+    /// A corrupt artifact is removed.
+    fn load(&self, key: u128) -> Option<Vec<u8>> {
+        let path = self.artifact_path(key);
+        let mut bytes = fs::read(&path).ok()?;
+        if !is_intact(&bytes) {
+            fs::remove_file(&path).ok();
+            return None;
+        }
+        if let Ok(file) = fs::File::options().write(true).open(&path) {
+            file.set_modified(std::time::SystemTime::now()).ok();
+        }
+        Some(bytes.split_off(CHECKSUM_LEN))
+    }
+
+    /// Store the artifact with the given key and evict old artifacts if over the size cap.
+    ///
+    /// Failing to write to the cache does not fail compilation, so errors are ignored.
+    fn store(&self, key: u128, bytes: &[u8]) {
+        // Write to a temporary file unique to this store first so that readers never observe a
+        // partial artifact, even if the same key is stored concurrently.
+        let path = self.artifact_path(key);
+        let id = TMP_COUNTER.fetch_add(1, Ordering::Relaxed);
+        let tmp_path = path.with_extension(format!("{}.{}.tmp", std::process::id(), id));
+        if write_artifact(&tmp_path, bytes).is_err() || fs::rename(&tmp_path, &path).is_err() {
+            fs::remove_file(&tmp_path).ok();
+            return;
+        }
+        self.evict(key).ok();
+    }
+
+    /// Remove the least recently used artifacts until the total size is within the cap.
+    ///
+    /// The artifact with the given key, which was just stored, is never removed, even if it alone
+    /// exceeds the cap. Artifacts last used at the same time are removed in order of their paths.
+    fn evict(&self, keep: u128) -> io::Result<()> {
+        let keep = self.artifact_path(keep);
+        let mut artifacts = self.artifacts()?;
+        let mut size: u64 = artifacts.iter().map(|(_, meta)| meta.len()).sum();
+        artifacts.sort_by(|(a, a_meta), (b, b_meta)| {
+            (a_meta.modified().ok(), a).cmp(&(b_meta.modified().ok(), b))
+        });
+        for (path, meta) in artifacts {
+            if size <= self.max_size {
+                break;
+            }
+            if path == keep {
+                continue;
+            }
+            fs::remove_file(path)?;
+            size -= meta.len();
+        }
+        Ok(())
+    }
+
+    /// The path and metadata of every artifact within the cache directory.
+    fn artifacts(&self) -> io::Result<Vec<(PathBuf, fs::Metadata)>> {
+        let mut artifacts = vec![];
+        for entry in fs::read_dir(&self.dir)? {
+            let entry = entry?;
+            let path = entry.path();
+            if path.extension().and_then(|s| s.to_str()) != Some(DISK_CACHE_EXT) {
+                continue;
+            }
+            artifacts.push((path, entry.metadata()?));
+        }
+        Ok(artifacts)
+    }
+}
+
+/// Write the checksum of the bytes followed by the bytes themselves to a new file at the path.
+fn write_artifact(path: &Path, bytes: &[u8]) -> io::Result<()> {
+    let mut file = fs::File::options()
+        .write(true)
+        .create_new(true)
+        .open(path)?;
+    file.write_all(&checksum(bytes).to_le_bytes())?;
+    file.write_all(bytes)?;
+    file.sync_all()
+}
+
+/// Whether or not the artifact's checksum matches its bytes.
+fn is_intact(artifact: &[u8]) -> bool {
+    if artifact.len() < CHECKSUM_LEN {
+        return false;
+    }
+    let (sum, bytes) = artifact.split_at(CHECKSUM_LEN);
+    sum == checksum(bytes).to_le_bytes()
+}
+
+fn checksum(bytes: &[u8]) -> u128 {
+    let mut hasher = ContentHasher::new();
+    hasher.write(bytes);
+    hasher.finish_u128()
+}
+
+impl Keyed {
+    /// Hash the content of the shader at the given path along with the options.
+    ///
+    /// GLSL is preprocessed up front so that the source with all includes expanded is hashed,
+    /// meaning a change to any included file changes the key.
+    fn new(path: &Path, options: &CompileOptions) -> Result<Self, CompileError> {
+        let mut hasher = ContentHasher::new();
+        NAGA_VERSION.hash(&mut hasher);
+        env!("CARGO_PKG_VERSION").hash(&mut hasher);
+        options.fingerprint(&mut hasher);
+        let language = SourceLanguage::from_path(path).unwrap_or(SourceLanguage::Glsl);
+        language.hash(&mut hasher);
+        let resolver = &*options.stage_resolver;
+        let source = match language {
+            SourceLanguage::Glsl => {
+                let (preprocessed, stage) = preprocess_and_resolve(path, options, resolver)?;
+                preprocessed.source.hash(&mut hasher);
+                stage.hash(&mut hasher);
+                KeyedSource::Glsl(preprocessed, stage)
+            }
+            // The stage of other languages falls back to that of the module's first entry point,
+            // which is determined by the content, so only the resolver's stage is hashed.
+            #[cfg(feature = "wgsl-in")]
+            SourceLanguage::Wgsl => {
+                let source = fs::read_to_string(path)?;
+                source.hash(&mut hasher);
+                resolver.resolve_stage(path, &source).hash(&mut hasher);
+                KeyedSource::Wgsl(source)
+            }
+            #[cfg(feature = "spv-in")]
+            SourceLanguage::Spirv => {
+                let bytes = fs::read(path)?;
+                bytes.hash(&mut hasher);
+                resolver.resolve_stage(path, "").hash(&mut hasher);
+                KeyedSource::Spirv(bytes)
+            }
+        };
+        let key = hasher.finish_u128();
+        Ok(Keyed { key, source })
+    }
+
+    /// Compile the source from which the key was hashed.
+    #[cfg_attr(
+        not(any(feature = "wgsl-in", feature = "spv-in")),
+        allow(unused_variables)
+    )]
+    fn compile(
+        self,
+        path: &Path,
+        options: &CompileOptions,
+    ) -> Result<CompiledShader, CompileError> {
+        match self.source {
+            KeyedSource::Glsl(preprocessed, stage) => {
+                compile_expanded(&preprocessed, stage, options)
+            }
+            #[cfg(feature = "wgsl-in")]
+            KeyedSource::Wgsl(source) => {
+                crate::compile_wgsl(path, source, options, &*options.stage_resolver)
+            }
+            #[cfg(feature = "spv-in")]
+            KeyedSource::Spirv(bytes) => {
+                crate::compile_spv(path, &bytes, options, &*options.stage_resolver)
+            }
+        }
+    }
+}
+
+impl ContentHasher {
+    const OFFSET_BASIS: u128 = 0x6c62272e07bb014262b821756295c58d;
+    const PRIME: u128 = 0x0000000001000000000000000000013b;
+
+    pub(crate) fn new() -> Self {
+        ContentHasher(Self::OFFSET_BASIS)
+    }
+
+    /// The full 128-bit hash of the bytes written so far.
+    pub(crate) fn finish_u128(&self) -> u128 {
+        self.0
+    }
+}
+
+impl Hasher for ContentHasher {
+    fn write(&mut self, bytes: &[u8]) {
+        for &byte in bytes {
+            self.0 ^= u128::from(byte);
+            self.0 = self.0.wrapping_mul(Self::PRIME);
+        }
+    }
+
+    fn finish(&self) -> u64 {
+        (self.0 ^ (self.0 >> 64)) as u64
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::common::TempDir;
+
+    /// Write a shader that includes `common.glsl` to the given directory, returning its path.
+    fn write_shader(dir: &Path) -> PathBuf {
+        fs::write(dir.join("common.glsl"), "// common\n").unwrap();
+        let source = "#version 450\n#include \"common.glsl\"\nvoid main() {}\n";
+        let path = dir.join("shader.frag");
+        fs::write(&path, source).unwrap();
+        path
+    }
+
+    #[test]
+    fn compile_cache_hits_unchanged_shader() {
+        let dir = TempDir::new("cache-hit");
+        let path = write_shader(&dir);
+        let cache = CompileCache::new(4);
+        let options = CompileOptions::default();
+        let first = cache.compile(&path, &options).unwrap();
+        let second = cache.compile(&path, &options).unwrap();
+        assert_eq!(first.bytes, second.bytes);
+        let stats = cache.stats();
+        assert_eq!((stats.hits, stats.misses, stats.entries), (1, 1, 1));
+    }
+
+    #[test]
+    fn compile_cache_misses_changed_include() {
+        let dir = TempDir::new("cache-include");
+        let path = write_shader(&dir);
+        let cache = CompileCache::new(4);
+        let options = CompileOptions::default();
+        cache.compile(&path, &options).unwrap();
+        let common = path.with_file_name("common.glsl");
+        fs::write(common, "// changed\n").unwrap();
+        cache.compile(&path, &options).unwrap();
+        let stats = cache.stats();
+        assert_eq!((stats.hits, stats.misses), (0, 2));
+    }
+
+    #[test]
+    fn compile_cache_misses_changed_options() {
+        let dir = TempDir::new("cache-options");
+        let path = write_shader(&dir);
+        let cache = CompileCache::new(4);
+        cache.compile(&path, &CompileOptions::default()).unwrap();
+        let options = CompileOptions::default().define("FOO", "1");
+        cache.compile(&path, &options).unwrap();
+        cache.compile(&path, &options).unwrap();
+        let stats = cache.stats();
+        assert_eq!((stats.hits, stats.misses, stats.entries), (1, 2, 2));
+    }
+
+    #[test]
+    fn compile_cache_evicts_oldest_entry() {
+        let dir = TempDir::new("cache-evict");
+        let path = write_shader(&dir);
+        let cache = CompileCache::new(1);
+        let options = CompileOptions::default();
+        let defined = CompileOptions::default().define("FOO", "1");
+        cache.compile(&path, &options).unwrap();
+        cache.compile(&path, &defined).unwrap();
+        assert_eq!(cache.stats().entries, 1);
+        cache.compile(&path, &options).unwrap();
+        assert_eq!(cache.stats().misses, 3);
+    }
+
+    #[test]
+    fn compile_cache_serves_disk_cache_and_skips_failures() {
+        let dir = TempDir::new("cache-disk");
+        let path = write_shader(&dir);
+        let disk = DiskCache::new(path.with_file_name("cache")).unwrap();
+        let options = CompileOptions::default().disk_cache(disk);
+        let bytes = CompileCache::new(4).compile_bytes(&path, &options).unwrap();
+        let cache = CompileCache::new(4);
+        assert_eq!(cache.compile_bytes(&path, &options).unwrap(), bytes);
+        assert_eq!(cache.stats().disk_hits, 1);
+        fs::write(&path, "#version 450\nvoid main() { broken }\n").unwrap();
+        assert!(cache.compile_bytes(&path, &options).is_err());
+        assert_eq!(cache.stats().entries, 0);
+    }
+
+    #[cfg(feature = "wgsl-in")]
+    #[test]
+    fn wgsl_key_includes_resolved_stage() {
+        let dir = TempDir::new("cache-wgsl-stage");
+        let path = dir.join("shader.wgsl");
+        fs::write(&path, "@fragment fn main() {}\n").unwrap();
+        let vertex = CompileOptions::default().stage_resolver(ShaderStage::Vertex);
+        let fragment = CompileOptions::default().stage_resolver(ShaderStage::Fragment);
+        let vertex = Keyed::new(&path, &vertex).unwrap();
+        let fragment = Keyed::new(&path, &fragment).unwrap();
+        assert_ne!(vertex.key, fragment.key);
+    }
+
+    #[test]
+    fn disk_cache_round_trip() {
+        let dir = TempDir::new("cache-round-trip");
+        let disk = DiskCache::new(&*dir).unwrap();
+        assert_eq!(disk.load(1), None);
+        disk.store(1, &[3, 2, 0x23, 7]);
+        assert_eq!(disk.load(1), Some(vec![3, 2, 0x23, 7]));
+    }
+
+    #[test]
+    fn disk_cache_rejects_corrupt_artifact() {
+        let dir = TempDir::new("cache-corrupt");
+        let disk = DiskCache::new(&*dir).unwrap();
+        disk.store(1, &[1, 2, 3, 4, 5, 6, 7, 8]);
+        let path = disk.artifact_path(1);
+        let mut artifact = fs::read(&path).unwrap();
+        artifact.truncate(artifact.len() - 3);
+        fs::write(&path, artifact).unwrap();
+        assert_eq!(disk.load(1), None);
+        assert!(!path.exists());
+    }
+
+    #[test]
+    fn disk_cache_concurrent_stores_of_same_key() {
+        let dir = TempDir::new("cache-concurrent");
+        let disk = DiskCache::new(&*dir).unwrap();
+        let outputs: Vec<Vec<u8>> = (0..8u8).map(|i| vec![i; 4096 * (i as usize + 1)]).collect();
+        std::thread::scope(|scope| {
+            for bytes in &outputs {
+                for _ in 0..4 {
+                    let disk = &disk;
+                    scope.spawn(move || disk.store(7, bytes));
+                }
+            }
+        });
+        let loaded = disk.load(7).unwrap();
+        assert!(outputs.contains(&loaded));
+        let leftovers = fs::read_dir(disk.dir()).unwrap().count();
+        assert_eq!(leftovers, 1);
+    }
+
+    #[test]
+    fn disk_cache_evicts_least_recently_used() {
+        let dir = TempDir::new("cache-disk-evict");
+        let disk = DiskCache::new(&*dir).unwrap().max_size(100);
+        disk.store(1, &[0; 40]);
+        disk.store(2, &[0; 40]);
+        disk.store(3, &[0; 40]);
+        assert_eq!(disk.load(1), None);
+        assert!(disk.load(3).is_some());
+        assert!(disk.size().unwrap() <= 100);
+    }
+
+    #[test]
+    fn disk_cache_never_evicts_the_stored_artifact() {
+        let dir = TempDir::new("cache-disk-evict-keep");
+        let disk = DiskCache::new(&*dir).unwrap().max_size(120);
+        disk.store(1, &[0; 40]);
+        disk.store(2, &[0; 40]);
+        // Make the existing artifacts appear more recently used than any new one.
+        let future = std::time::SystemTime::now() + std::time::Duration::from_secs(3600);
+        for key in &[1, 2] {
+            let file = fs::File::options()
+                .write(true)
+                .open(disk.artifact_path(*key))
+                .unwrap();
+            file.set_modified(future).unwrap();
+        }
+        disk.store(0, &[0; 40]);
+        // The tie between the others is broken by path.
+        assert!(disk.artifact_path(0).exists());
+        assert!(!disk.artifact_path(1).exists());
+        assert!(disk.artifact_path(2).exists());
+
+        // An artifact exceeding the cap by itself is kept until the next store.
+        disk.store(3, &[0; 200]);
+        assert!(disk.load(3).is_some());
+        assert_eq!(fs::read_dir(disk.dir()).unwrap().count(), 1);
+    }
+}
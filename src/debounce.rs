@@ -0,0 +1,206 @@
+//! Coalescing of the bursts of file system events that editors tend to produce on save.
+
+use std::path::PathBuf;
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+
+/// A source of time used to determine when touched shaders have settled.
+///
+/// `SystemClock` is used by default. `ManualClock` allows for testing debouncing
+/// deterministically.
+pub trait Clock: Send + Sync {
+    /// The current instant.
+    fn now(&self) -> Instant;
+}
+
+/// A `Clock` that reads the system's monotonic clock.
+#[derive(Clone, Copy, Debug, Default)]
+pub struct SystemClock;
+
+/// A `Clock` that only advances when told to.
+///
+/// Clones share the same underlying time, so a clone may be handed to a `Watch` while the original
+/// is used to advance time.
+#[derive(Clone, Debug)]
+pub struct ManualClock {
+    now: Arc<Mutex<Instant>>,
+}
+
+/// Tracks recently touched paths until they have gone untouched for the debounce window.
+pub(crate) struct Debouncer {
+    window: Duration,
+    clock: Arc<dyn Clock>,
+    touched: Vec<(PathBuf, Instant)>,
+}
+
+impl Clock for SystemClock {
+    fn now(&self) -> Instant {
+        Instant::now()
+    }
+}
+
+impl ManualClock {
+    /// Create a new manual clock starting at the current instant.
+    pub fn new() -> Self {
+        let now = Arc::new(Mutex::new(Instant::now()));
+        ManualClock { now }
+    }
+
+    /// Move the clock forward by the given duration.
+    pub fn advance(&self, duration: Duration) {
+        *self.now.lock().unwrap() += duration;
+    }
+}
+
+impl Default for ManualClock {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Clock for ManualClock {
+    fn now(&self) -> Instant {
+        *self.now.lock().unwrap()
+    }
+}
+
+impl Debouncer {
+    /// A debouncer with a zero-length window, in which touched paths settle immediately.
+    pub(crate) fn new() -> Self {
+        Debouncer {
+            window: Duration::from_secs(0),
+            clock: Arc::new(SystemClock),
+            touched: vec![],
+        }
+    }
+
+    pub(crate) fn window(&self) -> Duration {
+        self.window
+    }
+
+    pub(crate) fn set_window(&mut self, window: Duration) {
+        self.window = window;
+    }
+
+    pub(crate) fn set_clock(&mut self, clock: Arc<dyn Clock>) {
+        self.clock = clock;
+    }
+
+    /// Mark the given paths as touched now, restarting the window of any already pending.
+    pub(crate) fn touch<I>(&mut self, paths: I)
+    where
+        I: IntoIterator<Item = PathBuf>,
+    {
+        let now = self.clock.now();
+        for path in paths {
+            match self.touched.iter_mut().find(|(p, _)| *p == path) {
+                Some((_, instant)) => *instant = now,
+                None => self.touched.push((path, now)),
+            }
+        }
+    }
+
+    /// Remove and return all paths that have gone untouched for at least the debounce window, in
+    /// the order in which they were first touched.
+    pub(crate) fn take_settled(&mut self) -> Vec<PathBuf> {
+        let now = self.clock.now();
+        let window = self.window;
+        let mut settled = vec![];
+        self.touched.retain(|(path, instant)| {
+            if now.saturating_duration_since(*instant) >= window {
+                settled.push(path.clone());
+                false
+            } else {
+                true
+            }
+        });
+        settled
+    }
+
+    /// The time remaining until the next touched path settles, or `None` if there are no touched
+    /// paths.
+    pub(crate) fn time_until_settled(&self) -> Option<Duration> {
+        let now = self.clock.now();
+        self.touched
+            .iter()
+            .map(|(_, instant)| {
+                let elapsed = now.saturating_duration_since(*instant);
+                self.window.saturating_sub(elapsed)
+            })
+            .min()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const WINDOW: Duration = Duration::from_millis(100);
+
+    fn debouncer(clock: &ManualClock) -> Debouncer {
+        let mut debouncer = Debouncer::new();
+        debouncer.set_window(WINDOW);
+        debouncer.set_clock(Arc::new(clock.clone()));
+        debouncer
+    }
+
+    fn paths(names: &[&str]) -> Vec<PathBuf> {
+        names.iter().map(PathBuf::from).collect()
+    }
+
+    #[test]
+    fn burst_collapses_into_one_yield() {
+        let clock = ManualClock::new();
+        let mut debouncer = debouncer(&clock);
+        for _ in 0..10 {
+            debouncer.touch(paths(&["a.frag"]));
+            clock.advance(Duration::from_millis(5));
+        }
+        assert!(debouncer.take_settled().is_empty());
+        clock.advance(WINDOW);
+        assert_eq!(debouncer.take_settled(), paths(&["a.frag"]));
+        assert!(debouncer.take_settled().is_empty());
+        assert_eq!(debouncer.time_until_settled(), None);
+    }
+
+    #[test]
+    fn retouch_restarts_window() {
+        let clock = ManualClock::new();
+        let mut debouncer = debouncer(&clock);
+        debouncer.touch(paths(&["a.frag"]));
+        clock.advance(Duration::from_millis(80));
+        debouncer.touch(paths(&["a.frag"]));
+        clock.advance(Duration::from_millis(80));
+        assert!(debouncer.take_settled().is_empty());
+        assert_eq!(
+            debouncer.time_until_settled(),
+            Some(Duration::from_millis(20))
+        );
+        clock.advance(Duration::from_millis(20));
+        assert_eq!(debouncer.take_settled(), paths(&["a.frag"]));
+    }
+
+    #[test]
+    fn paths_settle_in_order_of_first_touch() {
+        let clock = ManualClock::new();
+        let mut debouncer = debouncer(&clock);
+        debouncer.touch(paths(&["b.frag", "a.frag"]));
+        clock.advance(Duration::from_millis(50));
+        debouncer.touch(paths(&["c.frag"]));
+        debouncer.touch(paths(&["b.frag"]));
+        clock.advance(Duration::from_millis(50));
+        assert_eq!(debouncer.take_settled(), paths(&["a.frag"]));
+        clock.advance(Duration::from_millis(50));
+        assert_eq!(debouncer.take_settled(), paths(&["b.frag", "c.frag"]));
+    }
+
+    #[test]
+    fn zero_window_yields_immediately() {
+        let clock = ManualClock::new();
+        let mut debouncer = debouncer(&clock);
+        debouncer.set_window(Duration::from_secs(0));
+        debouncer.touch(paths(&["a.frag", "b.frag"]));
+        assert_eq!(debouncer.time_until_settled(), Some(Duration::from_secs(0)));
+        assert_eq!(debouncer.take_settled(), paths(&["a.frag", "b.frag"]));
+    }
+}
@@ -0,0 +1,452 @@
+//! Structured, span-aware diagnostics describing why a shader failed to compile.
+//!
+//! See `CompileError::diagnostics`. The `Display` implementation of `Diagnostic` renders the
+//! diagnostic with source snippets and carets in the style of rustc.
+
+use crate::{CompileError, Preprocessed, ShaderStage};
+use std::fmt;
+use std::path::{Path, PathBuf};
+
+/// A summary of the compilation of a single shader file.
+///
+/// With the `serde` feature enabled this can be serialized, e.g. to stream the results of
+/// `Watch::compile_touched` to an editor as JSON.
+#[derive(Clone, Debug, PartialEq, Eq, Hash)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct CompileReport {
+    pub path: PathBuf,
+    /// Whether or not compilation succeeded.
+    pub success: bool,
+    /// Diagnostics describing why compilation failed, if it did.
+    pub diagnostics: Vec<Diagnostic>,
+}
+
+/// How serious a diagnostic is.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+#[cfg_attr(feature = "serde", serde(rename_all = "lowercase"))]
+pub enum Severity {
+    Error,
+    Warning,
+    Note,
+}
+
+/// A 1-based line and column within a source file, where columns are counted in characters.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, PartialOrd, Ord, Hash)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct Position {
+    pub line: usize,
+    pub column: usize,
+}
+
+/// A region of a source file.
+#[derive(Clone, Debug, PartialEq, Eq, Hash)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct SourceRange {
+    /// The file containing the region, or `None` if the source did not originate from a file.
+    pub path: Option<PathBuf>,
+    /// The position of the first character within the region.
+    pub start: Position,
+    /// The position one past the last character within the region.
+    pub end: Position,
+    /// The text of the line on which the region starts, or an empty string if unknown.
+    pub line_text: String,
+}
+
+/// A region of source along with a message describing its relevance to a diagnostic.
+#[derive(Clone, Debug, PartialEq, Eq, Hash)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct Label {
+    pub range: SourceRange,
+    pub message: String,
+}
+
+/// A single problem encountered while compiling a shader.
+#[derive(Clone, Debug, PartialEq, Eq, Hash)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct Diagnostic {
+    pub severity: Severity,
+    pub message: String,
+    /// The file to which the diagnostic relates, if any.
+    pub path: Option<PathBuf>,
+    /// The stage of the shader being compiled, if it was known at the time of the error.
+    pub stage: Option<ShaderStage>,
+    /// Regions of source related to the diagnostic. The first label, if any, is the primary one.
+    pub labels: Vec<Label>,
+    /// Additional context, e.g. the chain of errors leading to a validation failure.
+    pub notes: Vec<String>,
+}
+
+impl CompileReport {
+    /// Summarise the result of compiling the shader at the given path.
+    pub fn new<T>(path: &Path, result: &Result<T, CompileError>) -> Self {
+        let diagnostics = match *result {
+            Ok(_) => vec![],
+            Err(ref err) => err
+                .diagnostics()
+                .into_iter()
+                .map(|diag| match diag.path {
+                    Some(_) => diag,
+                    None => diag.with_path(Some(path)),
+                })
+                .collect(),
+        };
+        CompileReport {
+            path: path.to_path_buf(),
+            success: result.is_ok(),
+            diagnostics,
+        }
+    }
+}
+
+impl Severity {
+    fn as_str(&self) -> &'static str {
+        match *self {
+            Severity::Error => "error",
+            Severity::Warning => "warning",
+            Severity::Note => "note",
+        }
+    }
+}
+
+impl Diagnostic {
+    /// An error diagnostic with the given message and no location.
+    pub fn error<S>(message: S) -> Self
+    where
+        S: Into<String>,
+    {
+        Diagnostic {
+            severity: Severity::Error,
+            message: message.into(),
+            path: None,
+            stage: None,
+            labels: vec![],
+            notes: vec![],
+        }
+    }
+
+    /// The range of the primary label, if any.
+    pub fn primary_range(&self) -> Option<&SourceRange> {
+        self.labels.first().map(|label| &label.range)
+    }
+
+    pub(crate) fn with_path(mut self, path: Option<&Path>) -> Self {
+        self.path = path.map(Path::to_path_buf);
+        self
+    }
+
+    pub(crate) fn with_stage(mut self, stage: ShaderStage) -> Self {
+        self.stage = Some(stage);
+        self
+    }
+
+    pub(crate) fn with_label(mut self, range: SourceRange, message: String) -> Self {
+        if self.labels.is_empty() && range.path.is_some() {
+            self.path = range.path.clone();
+        }
+        self.labels.push(Label { range, message });
+        self
+    }
+
+    pub(crate) fn with_note(mut self, note: String) -> Self {
+        self.notes.push(note);
+        self
+    }
+
+    /// Diagnostics for each error produced by naga's GLSL frontend.
+    pub(crate) fn from_front(
+        errors: &[naga::front::glsl::Error],
+        source: &Preprocessed,
+    ) -> Vec<Self> {
+        errors
+            .iter()
+            .map(|err| {
+                let diag = Diagnostic::error(err.kind.to_string()).with_path(source.path());
+                match SourceRange::from_span(err.meta, source) {
+                    Some(range) => diag.with_label(range, String::new()),
+                    None => diag,
+                }
+            })
+            .collect()
+    }
+
+    /// A diagnostic for an error produced by naga's validator.
+    ///
+    /// Each span context becomes a label and the chain of underlying errors becomes notes.
+    pub(crate) fn from_validation(
+        err: &naga::WithSpan<naga::valid::ValidationError>,
+        source: &Preprocessed,
+    ) -> Self {
+        let inner = err.as_inner();
+        let mut diag = Diagnostic::error(inner.to_string()).with_path(source.path());
+        for (span, description) in err.spans() {
+            if let Some(range) = SourceRange::from_span(*span, source) {
+                diag = diag.with_label(range, description.clone());
+            }
+        }
+        let mut cause = std::error::Error::source(inner);
+        while let Some(err) = cause {
+            diag = diag.with_note(err.to_string());
+            cause = err.source();
+        }
+        diag
+    }
+
+    /// A diagnostic for an error produced by naga's WGSL frontend.
+    #[cfg(feature = "wgsl-in")]
+    pub(crate) fn from_wgsl(err: &naga::front::wgsl::ParseError, source: &Preprocessed) -> Self {
+        let mut diag = Diagnostic::error(err.message()).with_path(source.path());
+        for (span, message) in err.labels() {
+            if let Some(range) = SourceRange::from_span(span, source) {
+                diag = diag.with_label(range, message.to_string());
+            }
+        }
+        diag
+    }
+}
+
+impl SourceRange {
+    /// The range within the original source file corresponding to the given span of expanded
+    /// source, or `None` if the span is undefined.
+    pub(crate) fn from_span(span: naga::Span, source: &Preprocessed) -> Option<Self> {
+        let range = span.to_range()?;
+        let (start_line, start_col, line_text) = locate(&source.source, range.start);
+        let (end_line, end_col, _) = locate(&source.source, range.end);
+        let (path, line) = source.line_origin(start_line)?;
+        let start = Position {
+            line,
+            column: start_col,
+        };
+        // Ranges spanning multiple files are clamped to the end of the first line.
+        let end = match source.line_origin(end_line) {
+            Some((end_path, line)) if end_path == path => Position {
+                line,
+                column: end_col,
+            },
+            _ => Position {
+                line,
+                column: line_text.chars().count() + 1,
+            },
+        };
+        Some(SourceRange {
+            path: path.map(Path::to_path_buf),
+            start,
+            end,
+            line_text: line_text.to_string(),
+        })
+    }
+
+    /// A range covering the given 1-based line of the given file, without any line text.
+    pub(crate) fn line(path: Option<&Path>, line: usize) -> Self {
+        let start = Position { line, column: 1 };
+        SourceRange {
+            path: path.map(Path::to_path_buf),
+            start,
+            end: start,
+            line_text: String::new(),
+        }
+    }
+}
+
+impl fmt::Display for Position {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}:{}", self.line, self.column)
+    }
+}
+
+impl fmt::Display for SourceRange {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self.path {
+            Some(ref path) => write!(f, "{}:{}", path.display(), self.start),
+            None => write!(f, "<string>:{}", self.start),
+        }
+    }
+}
+
+impl fmt::Display for Diagnostic {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        writeln!(f, "{}: {}", self.severity.as_str(), self.message)?;
+        let gutter = self
+            .labels
+            .iter()
+            .map(|label| label.range.start.line.to_string().len())
+            .max()
+            .unwrap_or(1);
+        let pad = " ".repeat(gutter);
+        if self.labels.is_empty() {
+            if let Some(ref path) = self.path {
+                writeln!(f, "{}--> {}", pad, path.display())?;
+            }
+        }
+        for label in &self.labels {
+            let range = &label.range;
+            writeln!(f, "{}--> {}", pad, range)?;
+            if range.line_text.is_empty() {
+                continue;
+            }
+            let line_len = range.line_text.chars().count();
+            let start = range.start.column.min(line_len + 1);
+            let end = if range.end.line == range.start.line {
+                range.end.column.min(line_len + 1)
+            } else {
+                line_len + 1
+            };
+            let carets = "^".repeat(end.saturating_sub(start).max(1));
+            writeln!(f, "{} |", pad)?;
+            writeln!(
+                f,
+                "{:>w$} | {}",
+                range.start.line,
+                range.line_text,
+                w = gutter
+            )?;
+            let indent = " ".repeat(start.saturating_sub(1));
+            let marker = format!("{} | {}{} {}", pad, indent, carets, label.message);
+            writeln!(f, "{}", marker.trim_end())?;
+        }
+        if !self.notes.is_empty() {
+            writeln!(f, "{} |", pad)?;
+        }
+        for note in &self.notes {
+            writeln!(f, "{} = note: {}", pad, note)?;
+        }
+        Ok(())
+    }
+}
+
+/// The 0-based line, 1-based character column and line text of the given byte offset.
+fn locate(source: &str, offset: usize) -> (usize, usize, &str) {
+    let mut offset = offset.min(source.len());
+    while !source.is_char_boundary(offset) {
+        offset -= 1;
+    }
+    let line_start = source[..offset].rfind('\n').map(|ix| ix + 1).unwrap_or(0);
+    let line_end = source[line_start..]
+        .find('\n')
+        .map(|ix| line_start + ix)
+        .unwrap_or(source.len());
+    let line = source[..line_start].matches('\n').count();
+    let column = source[line_start..offset].chars().count() + 1;
+    (line, column, &source[line_start..line_end])
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::common::TempDir;
+
+    const OUT_OF_BOUNDS: &str = "    c = vec4(float(a[3]));";
+
+    fn fragment_error(source: &str) -> Vec<Diagnostic> {
+        crate::compile_str(source, ShaderStage::Fragment)
+            .unwrap_err()
+            .diagnostics()
+    }
+
+    fn range(line: usize, start: usize, end: usize, line_text: &str) -> SourceRange {
+        SourceRange {
+            path: None,
+            start: Position {
+                line,
+                column: start,
+            },
+            end: Position { line, column: end },
+            line_text: line_text.to_string(),
+        }
+    }
+
+    #[test]
+    fn labels_are_rendered_with_carets() {
+        let diag = Diagnostic::error("bad thing")
+            .with_label(range(12, 5, 8, "let foo = 1;"), "here".to_string())
+            .with_note("because".to_string());
+        let expected = "\
+error: bad thing
+  --> <string>:12:5
+   |
+12 | let foo = 1;
+   |     ^^^ here
+   |
+   = note: because
+";
+        assert_eq!(diag.to_string(), expected);
+    }
+
+    #[test]
+    fn labels_at_column_zero_are_rendered_at_the_start_of_the_line() {
+        let diag = Diagnostic::error("bad thing").with_label(range(1, 0, 0, "foo"), String::new());
+        let expected = "\
+error: bad thing
+ --> <string>:1:0
+  |
+1 | foo
+  | ^
+";
+        assert_eq!(diag.to_string(), expected);
+    }
+
+    #[test]
+    fn frontend_errors_are_located() {
+        let source = "#version 450\nvoid main() {\n    float x = ;\n}\n";
+        let diagnostics = fragment_error(source);
+        let range = diagnostics[0].primary_range().unwrap();
+        assert_eq!(range.path, None);
+        assert_eq!(range.start.line, 3);
+        assert_eq!(range.line_text, "    float x = ;");
+    }
+
+    #[test]
+    fn validation_errors_are_located() {
+        let source = format!(
+            "#version 450\nlayout(location = 0) out vec4 c;\nvoid main() {{\n    int a[2];\n{}\n}}\n",
+            OUT_OF_BOUNDS
+        );
+        let diagnostics = fragment_error(&source);
+        assert_eq!(diagnostics.len(), 1);
+        let diag = &diagnostics[0];
+        assert_eq!(diag.stage, Some(ShaderStage::Fragment));
+        assert_eq!(diag.labels[0].range, range(3, 1, 12, "void main() {"));
+        assert_eq!(diag.labels[1].range, range(5, 21, 24, OUT_OF_BOUNDS));
+        assert!(diag.notes.iter().any(|note| note.contains("out of")));
+    }
+
+    #[test]
+    fn errors_within_includes_are_located_in_the_included_file() {
+        let dir = TempDir::new("diagnostic-include");
+        let main = dir.join("main.frag");
+        let lib = dir.join("lib.glsl");
+        let source = "#version 450\nlayout(location = 0) out vec4 c;\n#include \"lib.glsl\"\n";
+        std::fs::write(&main, source).unwrap();
+        let lib_source = format!("void main() {{\n    int a[2];\n{}\n}}\n", OUT_OF_BOUNDS);
+        std::fs::write(&lib, lib_source).unwrap();
+        let options = crate::CompileOptions::default();
+        let err = crate::compile_reflected(&main, &options).unwrap_err();
+        let diagnostics = err.diagnostics();
+        let diag = &diagnostics[0];
+        assert_eq!(diag.path.as_deref(), Some(lib.as_path()));
+        let expected = SourceRange {
+            path: Some(lib.clone()),
+            ..range(3, 21, 24, OUT_OF_BOUNDS)
+        };
+        assert_eq!(diag.labels[1].range, expected);
+    }
+
+    #[test]
+    fn spans_are_mapped_through_line_origins() {
+        let dir = TempDir::new("diagnostic-span");
+        let main = dir.join("main.frag");
+        let lib = dir.join("lib.glsl");
+        std::fs::write(&main, "one\n#include \"lib.glsl\"\nfour\n").unwrap();
+        std::fs::write(&lib, "two\nthree\n").unwrap();
+        let pp = crate::preprocess_file(&main, &[]).unwrap();
+        // "three" spans bytes 8..13 of the expanded source.
+        let range = SourceRange::from_span(naga::Span::new(8, 13), &pp).unwrap();
+        assert_eq!(range.path.as_deref(), Some(lib.as_path()));
+        assert_eq!((range.start.line, range.start.column), (2, 1));
+        assert_eq!((range.end.line, range.end.column), (2, 6));
+        // Spans into another file are clamped to the end of the first line.
+        let range = SourceRange::from_span(naga::Span::new(8, 18), &pp).unwrap();
+        assert_eq!((range.end.line, range.end.column), (2, 6));
+        assert!(SourceRange::from_span(naga::Span::default(), &pp).is_none());
+    }
+}
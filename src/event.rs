@@ -0,0 +1,272 @@
+//! Typed shader file events derived from notify's events.
+//!
+//! Unlike touched paths, shader events also report shaders that were removed or renamed, which
+//! allows for discarding anything that was built from them.
+
+use notify::event::{EventKind, ModifyKind, RenameMode};
+use std::path::{Path, PathBuf};
+
+/// A change to a shader file beneath one of the watched paths.
+#[derive(Clone, Debug, PartialEq, Eq, Hash)]
+pub enum ShaderEvent {
+    /// A new shader file was created.
+    Created(PathBuf),
+    /// The contents or metadata of an existing shader file changed.
+    Modified(PathBuf),
+    /// A shader file was removed.
+    Removed(PathBuf),
+    /// A shader file was renamed or moved.
+    Renamed { from: PathBuf, to: PathBuf },
+}
+
+impl ShaderEvent {
+    /// The path at which the shader now resides, or for `Removed`, the path at which it resided.
+    pub fn path(&self) -> &Path {
+        match *self {
+            ShaderEvent::Created(ref path)
+            | ShaderEvent::Modified(ref path)
+            | ShaderEvent::Removed(ref path) => path,
+            ShaderEvent::Renamed { ref to, .. } => to,
+        }
+    }
+
+    /// The path at which the shader no longer resides, if any.
+    ///
+    /// This is the removed path for `Removed` and the old path for `Renamed`.
+    pub fn removed_path(&self) -> Option<&Path> {
+        match *self {
+            ShaderEvent::Removed(ref path) | ShaderEvent::Renamed { from: ref path, .. } => {
+                Some(path)
+            }
+            ShaderEvent::Created(_) | ShaderEvent::Modified(_) => None,
+        }
+    }
+}
+
+/// Derive the shader events described by the given notify event.
+///
+/// Paths that are not shaders according to `is_shader` are ignored, so a rename of a shader to a
+/// path that is not a shader is reported as a removal, and vice versa as a creation. Access events
+/// are ignored.
+pub(crate) fn shader_events(
+    event: &notify::Event,
+    is_shader: &dyn Fn(&Path) -> bool,
+) -> Vec<ShaderEvent> {
+    let shaders = event.paths.iter().filter(|p| is_shader(p)).cloned();
+    match event.kind {
+        EventKind::Access(_) => vec![],
+        EventKind::Create(_) => shaders.map(ShaderEvent::Created).collect(),
+        EventKind::Remove(_) => shaders.map(ShaderEvent::Removed).collect(),
+        // Each side of the rename has already been reported by a preceding `From` and `To` event.
+        EventKind::Modify(ModifyKind::Name(RenameMode::Both)) => match event.paths[..] {
+            [ref from, ref to] if is_shader(from) && is_shader(to) => {
+                let (from, to) = (from.clone(), to.clone());
+                vec![ShaderEvent::Renamed { from, to }]
+            }
+            _ => vec![],
+        },
+        EventKind::Modify(ModifyKind::Name(RenameMode::From)) => {
+            shaders.map(ShaderEvent::Removed).collect()
+        }
+        EventKind::Modify(ModifyKind::Name(RenameMode::To)) => {
+            shaders.map(ShaderEvent::Created).collect()
+        }
+        // Some platforms cannot tell which side of a rename a path is on, so check whether or not
+        // the path still exists.
+        EventKind::Modify(ModifyKind::Name(_)) => shaders
+            .map(|path| {
+                if path.is_file() {
+                    ShaderEvent::Created(path)
+                } else {
+                    ShaderEvent::Removed(path)
+                }
+            })
+            .collect(),
+        // If the shader has since been removed, the removal is reported by a following event.
+        EventKind::Modify(_) => shaders
+            .filter(|path| path.is_file())
+            .map(ShaderEvent::Modified)
+            .collect(),
+        EventKind::Any | EventKind::Other => shaders
+            .map(|path| {
+                if path.is_file() {
+                    ShaderEvent::Modified(path)
+                } else {
+                    ShaderEvent::Removed(path)
+                }
+            })
+            .collect(),
+    }
+}
+
+/// Queue the given shader event, coalescing it with those already queued for the same path.
+///
+/// Apart from renames, at most one event is queued per path, so the queue remains bounded even if
+/// shader events are never received.
+pub(crate) fn queue_shader_event(queue: &mut Vec<ShaderEvent>, event: ShaderEvent) {
+    let last_ix = |queue: &[ShaderEvent], path: &Path| queue.iter().rposition(|e| e.path() == path);
+    match event {
+        // The shader is already known to have changed, or if it was removed, has been replaced,
+        // e.g. by an editor's atomic save.
+        ShaderEvent::Created(ref path) | ShaderEvent::Modified(ref path) => {
+            if let Some(ix) = last_ix(queue, path) {
+                if let ShaderEvent::Removed(_) = queue[ix] {
+                    queue[ix] = ShaderEvent::Modified(path.clone());
+                }
+                return;
+            }
+        }
+        // The removal supersedes any creation or modification of the shader.
+        ShaderEvent::Removed(ref path) => {
+            if let Some(ix) = last_ix(queue, path) {
+                match queue[ix] {
+                    ShaderEvent::Removed(_) => return,
+                    ShaderEvent::Created(_) | ShaderEvent::Modified(_) => {
+                        queue.remove(ix);
+                    }
+                    ShaderEvent::Renamed { .. } => (),
+                }
+            }
+        }
+        // Replace the halves of the rename that some platforms report beforehand.
+        ShaderEvent::Renamed { ref from, ref to } => {
+            let removed = ShaderEvent::Removed(from.clone());
+            let created = ShaderEvent::Created(to.clone());
+            for half in [removed, created] {
+                if let Some(ix) = last_ix(queue, half.path()) {
+                    if queue[ix] == half {
+                        queue.remove(ix);
+                    }
+                }
+            }
+        }
+    }
+    queue.push(event);
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use notify::event::{CreateKind, DataChange, RemoveKind};
+
+    fn queue(events: Vec<ShaderEvent>) -> Vec<ShaderEvent> {
+        let mut queue = vec![];
+        for event in events {
+            queue_shader_event(&mut queue, event);
+        }
+        queue
+    }
+
+    fn path(name: &str) -> PathBuf {
+        PathBuf::from(name)
+    }
+
+    fn is_shader(path: &Path) -> bool {
+        path.extension().is_some_and(|ext| ext == "frag")
+    }
+
+    fn notify_event(kind: EventKind, paths: &[&str]) -> notify::Event {
+        let event = notify::Event::new(kind);
+        paths.iter().fold(event, |event, p| event.add_path(path(p)))
+    }
+
+    #[test]
+    fn repeated_atomic_saves_coalesce() {
+        let a = path("a.frag");
+        let saves = (0..20).map(|_| ShaderEvent::Created(a.clone())).collect();
+        assert_eq!(queue(saves), vec![ShaderEvent::Created(a)]);
+    }
+
+    #[test]
+    fn repeated_modifications_coalesce() {
+        let a = path("a.frag");
+        let events = vec![
+            ShaderEvent::Modified(a.clone()),
+            ShaderEvent::Modified(a.clone()),
+            ShaderEvent::Created(a.clone()),
+        ];
+        assert_eq!(queue(events), vec![ShaderEvent::Modified(a)]);
+    }
+
+    #[test]
+    fn removal_then_creation_becomes_modification() {
+        let a = path("a.frag");
+        let events = vec![
+            ShaderEvent::Removed(a.clone()),
+            ShaderEvent::Created(a.clone()),
+        ];
+        assert_eq!(queue(events), vec![ShaderEvent::Modified(a)]);
+    }
+
+    #[test]
+    fn removal_supersedes_earlier_events() {
+        let (a, b) = (path("a.frag"), path("b.frag"));
+        let events = vec![
+            ShaderEvent::Modified(a.clone()),
+            ShaderEvent::Created(b.clone()),
+            ShaderEvent::Removed(a.clone()),
+            ShaderEvent::Removed(a.clone()),
+        ];
+        let expected = vec![ShaderEvent::Created(b), ShaderEvent::Removed(a)];
+        assert_eq!(queue(events), expected);
+    }
+
+    #[test]
+    fn rename_replaces_its_halves() {
+        let (a, b, c) = (path("a.frag"), path("b.frag"), path("c.frag"));
+        let events = vec![
+            ShaderEvent::Modified(c.clone()),
+            ShaderEvent::Removed(a.clone()),
+            ShaderEvent::Created(b.clone()),
+            ShaderEvent::Renamed {
+                from: a.clone(),
+                to: b.clone(),
+            },
+        ];
+        let expected = vec![
+            ShaderEvent::Modified(c),
+            ShaderEvent::Renamed { from: a, to: b },
+        ];
+        assert_eq!(queue(events), expected);
+    }
+
+    #[test]
+    fn rename_is_only_paired_between_shaders() {
+        let both = EventKind::Modify(ModifyKind::Name(RenameMode::Both));
+        let event = notify_event(both, &["a.frag", "b.frag"]);
+        let renamed = ShaderEvent::Renamed {
+            from: path("a.frag"),
+            to: path("b.frag"),
+        };
+        assert_eq!(shader_events(&event, &is_shader), vec![renamed]);
+        let event = notify_event(both, &["a.frag.tmp", "a.frag"]);
+        assert!(shader_events(&event, &is_shader).is_empty());
+    }
+
+    #[test]
+    fn rename_halves_become_removal_and_creation() {
+        let from = EventKind::Modify(ModifyKind::Name(RenameMode::From));
+        let to = EventKind::Modify(ModifyKind::Name(RenameMode::To));
+        let removed = shader_events(&notify_event(from, &["a.frag"]), &is_shader);
+        let created = shader_events(&notify_event(to, &["b.frag"]), &is_shader);
+        assert_eq!(removed, vec![ShaderEvent::Removed(path("a.frag"))]);
+        assert_eq!(created, vec![ShaderEvent::Created(path("b.frag"))]);
+    }
+
+    #[test]
+    fn non_shader_and_access_events_are_ignored() {
+        let create = EventKind::Create(CreateKind::File);
+        let event = notify_event(create, &["a.txt", "b.frag"]);
+        let expected = vec![ShaderEvent::Created(path("b.frag"))];
+        assert_eq!(shader_events(&event, &is_shader), expected);
+        let remove = EventKind::Remove(RemoveKind::File);
+        let event = notify_event(remove, &["a.frag"]);
+        let expected = vec![ShaderEvent::Removed(path("a.frag"))];
+        assert_eq!(shader_events(&event, &is_shader), expected);
+        let access = EventKind::Access(notify::event::AccessKind::Any);
+        assert!(shader_events(&notify_event(access, &["a.frag"]), &is_shader).is_empty());
+        // A modification of a shader that no longer exists is reported by the removal instead.
+        let modify = EventKind::Modify(ModifyKind::Data(DataChange::Any));
+        assert!(shader_events(&notify_event(modify, &["gone.frag"]), &is_shader).is_empty());
+    }
+}
@@ -0,0 +1,80 @@
+//! Retention of the last successfully compiled output of each shader, so that applications may
+//! keep rendering with it while a broken shader is being fixed.
+//!
+//! See `Watch::compile_touched_with_fallback`.
+
+use crate::{CompileError, Diagnostic};
+use std::collections::HashMap;
+use std::path::{Path, PathBuf};
+use std::sync::Mutex;
+
+/// The result of compiling a touched shader, along with the shader's last successful output.
+#[derive(Debug)]
+pub struct CompileOutcome {
+    /// The path of the touched shader.
+    pub path: PathBuf,
+    /// The result of compiling the shader's current source.
+    pub result: Result<Vec<u8>, CompileError>,
+    /// The output of the most recent successful compilation of the shader prior to this one, if
+    /// any.
+    pub last_good: Option<Vec<u8>>,
+    /// Mismatches between the interface of the shader and those of the other stages of its
+    /// registered programs. Empty if the shader failed to compile. See `Watch::add_program`.
+    pub interface: Vec<Diagnostic>,
+}
+
+/// The last successfully compiled output of each shader compiled by a `Watch`.
+#[derive(Debug, Default)]
+pub(crate) struct LastGood {
+    outputs: Mutex<HashMap<PathBuf, Vec<u8>>>,
+}
+
+impl CompileOutcome {
+    /// The output to use: the newly compiled output, or the last good output if compilation
+    /// failed.
+    ///
+    /// Returns `None` if the shader has never compiled successfully.
+    pub fn bytes(&self) -> Option<&[u8]> {
+        match self.result {
+            Ok(ref bytes) => Some(bytes),
+            Err(_) => self.last_good.as_deref(),
+        }
+    }
+
+    /// The error produced by compiling the shader's current source, if any.
+    pub fn error(&self) -> Option<&CompileError> {
+        self.result.as_ref().err()
+    }
+}
+
+impl LastGood {
+    /// The last successful output of the shader at the given path.
+    pub(crate) fn get(&self, path: &Path) -> Option<Vec<u8>> {
+        self.outputs.lock().unwrap().get(path).cloned()
+    }
+
+    /// Record the result of compiling the shader at the given path, returning the shader's
+    /// previous successful output.
+    pub(crate) fn record(
+        &self,
+        path: &Path,
+        result: &Result<Vec<u8>, CompileError>,
+    ) -> Option<Vec<u8>> {
+        let mut outputs = self.outputs.lock().unwrap();
+        match *result {
+            Ok(ref bytes) => outputs.insert(path.to_path_buf(), bytes.clone()),
+            Err(_) => outputs.get(path).cloned(),
+        }
+    }
+
+    /// Record the output of successfully compiling the shader at the given path.
+    pub(crate) fn insert(&self, path: &Path, bytes: &[u8]) {
+        let mut outputs = self.outputs.lock().unwrap();
+        outputs.insert(path.to_path_buf(), bytes.to_vec());
+    }
+
+    /// Forget the outputs of all shaders.
+    pub(crate) fn clear(&self) {
+        self.outputs.lock().unwrap().clear();
+    }
+}
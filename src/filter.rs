@@ -0,0 +1,511 @@
+//! Configuration of which files beneath the watched paths are treated as shaders.
+//!
+//! See `FileFilter` and `Watch::set_file_filter`.
+
+use crate::{ShaderStage, SourceLanguage, StageResolver};
+use std::collections::HashMap;
+use std::path::{Component, Path, PathBuf};
+use std::sync::{Arc, Mutex};
+
+/// The names of the ignore files honored when enabled via `FileFilter::ignore_files`.
+const IGNORE_FILES: &[&str] = &[".gitignore", ".ignore"];
+
+/// Determines which files beneath the watched paths are treated as shaders.
+///
+/// By default, any file with the extension of a supported `SourceLanguage` is a shader. Further
+/// extensions may be added via `extension`, in which case the file is compiled as GLSL. Shaders
+/// may then be narrowed down via `include` and `exclude` glob patterns and `.gitignore` or
+/// `.ignore` files.
+///
+/// Glob patterns containing a `/` are matched against the path relative to the watched path that
+/// contains it, while all other patterns are matched against the file name. `*` and `?` match
+/// within a single path component, `**` matches any number of components and `[...]` matches a
+/// single character of the given set or range.
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct FileFilter {
+    extensions: Vec<(String, Option<ShaderStage>)>,
+    include: Vec<String>,
+    exclude: Vec<String>,
+    ignore_files: bool,
+}
+
+/// Resolves the stage of shaders whose extension was given a stage via `FileFilter::extension`,
+/// falling back to another resolver for all others.
+pub(crate) struct ExtensionStages {
+    filter: FileFilter,
+    fallback: Arc<dyn StageResolver>,
+}
+
+/// The rules parsed from the ignore files within each directory, read upon first use.
+///
+/// Entries are invalidated via `invalidate` as notify reports changes to the ignore files.
+#[derive(Default)]
+pub(crate) struct IgnoreCache {
+    rules: Mutex<HashMap<PathBuf, Arc<Vec<IgnoreRule>>>>,
+}
+
+/// A single pattern parsed from an ignore file.
+struct IgnoreRule {
+    /// The directory containing the ignore file.
+    dir: PathBuf,
+    pattern: String,
+    /// Whether the pattern is matched against the path relative to `dir` rather than the name.
+    anchored: bool,
+    dir_only: bool,
+    negated: bool,
+}
+
+impl FileFilter {
+    /// The default filter, accepting all files with the extension of a supported language.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Treat files with the given extension as GLSL shaders of the given stage.
+    ///
+    /// The extension may be compound, e.g. `"frag.in"`. If `stage` is `None`, the stage is
+    /// determined by the stage resolver, e.g. via a `#pragma shader_stage(...)` directive. Where
+    /// multiple extensions match a file, the longest is used.
+    pub fn extension<S>(mut self, ext: S, stage: Option<ShaderStage>) -> Self
+    where
+        S: Into<String>,
+    {
+        let ext = ext.into();
+        let ext = ext.trim_start_matches('.').to_string();
+        self.extensions.retain(|(e, _)| *e != ext);
+        self.extensions.push((ext, stage));
+        self
+    }
+
+    /// Only treat files matching at least one of the given glob patterns as shaders.
+    pub fn include<S>(mut self, pattern: S) -> Self
+    where
+        S: Into<String>,
+    {
+        self.include.push(pattern.into());
+        self
+    }
+
+    /// Never treat files matching the given glob pattern as shaders.
+    ///
+    /// Directories matching the pattern are skipped entirely, e.g. `"target"`.
+    pub fn exclude<S>(mut self, pattern: S) -> Self
+    where
+        S: Into<String>,
+    {
+        self.exclude.push(pattern.into());
+        self
+    }
+
+    /// Whether or not to honor `.gitignore` and `.ignore` files within the watched directories.
+    ///
+    /// A subset of the gitignore format is supported: comments, `!` negation, trailing `/` for
+    /// directories and leading or inner `/` for anchoring. Ignore files outside of the watched
+    /// directories are not consulted.
+    pub fn ignore_files(mut self, honor: bool) -> Self {
+        self.ignore_files = honor;
+        self
+    }
+
+    /// The stage given to the longest of the filter's extensions that the path has, if any.
+    pub fn stage(&self, path: &Path) -> Option<ShaderStage> {
+        self.matching_extension(path).and_then(|(_, stage)| *stage)
+    }
+
+    /// Whether or not the file at the given path, beneath one of the given watched paths, is
+    /// treated as a shader.
+    ///
+    /// The file need not exist, e.g. so that removed shaders can be recognised.
+    pub(crate) fn is_shader(&self, path: &Path, roots: &[PathBuf], ignores: &IgnoreCache) -> bool {
+        let has_ext =
+            SourceLanguage::from_path(path).is_some() || self.matching_extension(path).is_some();
+        if !has_ext {
+            return false;
+        }
+        let rel = relative_path(path, roots);
+        if !self.include.is_empty() && !self.include.iter().any(|p| glob_matches(p, &rel)) {
+            return false;
+        }
+        !self.is_excluded(path, false, roots, ignores)
+    }
+
+    /// Whether or not the given file or directory is excluded by a glob pattern or ignore file.
+    ///
+    /// Excluded directories need not be searched for shaders.
+    pub(crate) fn is_excluded(
+        &self,
+        path: &Path,
+        is_dir: bool,
+        roots: &[PathBuf],
+        ignores: &IgnoreCache,
+    ) -> bool {
+        let rel = relative_path(path, roots);
+        if self.exclude.iter().any(|p| glob_matches(p, &rel)) {
+            return true;
+        }
+        if !self.ignore_files {
+            return false;
+        }
+        match root_of(path, roots) {
+            Some(root) => is_ignored(root, path, is_dir, ignores),
+            None => false,
+        }
+    }
+
+    /// Whether or not any of the filter's extensions were given a stage.
+    pub(crate) fn has_stages(&self) -> bool {
+        self.extensions.iter().any(|(_, stage)| stage.is_some())
+    }
+
+    fn matching_extension(&self, path: &Path) -> Option<&(String, Option<ShaderStage>)> {
+        let name = path.file_name()?.to_str()?;
+        self.extensions
+            .iter()
+            .filter(|(ext, _)| {
+                name.len() > ext.len() + 1
+                    && name.ends_with(ext.as_str())
+                    && name[..name.len() - ext.len()].ends_with('.')
+            })
+            .max_by_key(|(ext, _)| ext.len())
+    }
+}
+
+impl ExtensionStages {
+    pub(crate) fn new(filter: FileFilter, fallback: Arc<dyn StageResolver>) -> Self {
+        ExtensionStages { filter, fallback }
+    }
+}
+
+impl StageResolver for ExtensionStages {
+    fn resolve_stage(&self, path: &Path, source: &str) -> Option<ShaderStage> {
+        self.filter
+            .stage(path)
+            .or_else(|| self.fallback.resolve_stage(path, source))
+    }
+}
+
+impl IgnoreCache {
+    /// Forget the rules of any directory whose ignore files may have changed given the paths of a
+    /// notify event.
+    pub(crate) fn invalidate(&self, paths: &[PathBuf]) {
+        let mut rules = self.rules.lock().unwrap();
+        if rules.is_empty() {
+            return;
+        }
+        for path in paths {
+            let is_ignore_file = path
+                .file_name()
+                .and_then(|name| name.to_str())
+                .is_some_and(|name| IGNORE_FILES.contains(&name));
+            match path.parent() {
+                Some(dir) if is_ignore_file => {
+                    rules.remove(dir);
+                }
+                // The path may be a directory that was removed or renamed along with its files.
+                _ => rules.retain(|dir, _| !dir.starts_with(path)),
+            }
+        }
+    }
+
+    /// Forget the rules of all directories.
+    pub(crate) fn clear(&self) {
+        self.rules.lock().unwrap().clear();
+    }
+
+    /// The rules of the ignore files within the given directory.
+    fn rules(&self, dir: &Path) -> Arc<Vec<IgnoreRule>> {
+        if let Some(rules) = self.rules.lock().unwrap().get(dir) {
+            return rules.clone();
+        }
+        let rules = Arc::new(read_ignore_files(dir));
+        let mut cached = self.rules.lock().unwrap();
+        cached.insert(dir.to_path_buf(), rules.clone());
+        rules
+    }
+}
+
+impl IgnoreRule {
+    /// Parse a single line of an ignore file within the given directory.
+    fn parse(dir: &Path, line: &str) -> Option<Self> {
+        let line = line.trim_end();
+        if line.is_empty() || line.starts_with('#') {
+            return None;
+        }
+        let (negated, line) = match line.strip_prefix('!') {
+            Some(rest) => (true, rest),
+            None => (false, line.strip_prefix('\\').unwrap_or(line)),
+        };
+        let (dir_only, line) = match line.strip_suffix('/') {
+            Some(rest) => (true, rest),
+            None => (false, line),
+        };
+        let anchored = line.contains('/');
+        let pattern = line.trim_start_matches('/').to_string();
+        if pattern.is_empty() {
+            return None;
+        }
+        Some(IgnoreRule {
+            dir: dir.to_path_buf(),
+            pattern,
+            anchored,
+            dir_only,
+            negated,
+        })
+    }
+
+    /// Whether or not the rule matches the given path.
+    fn matches(&self, path: &Path, is_dir: bool) -> bool {
+        if self.dir_only && !is_dir {
+            return false;
+        }
+        let rel = match path.strip_prefix(&self.dir) {
+            Ok(rel) => slash_path(rel),
+            Err(_) => return false,
+        };
+        if self.anchored {
+            glob_matches(&self.pattern, &rel)
+        } else {
+            let name = rel.rsplit('/').next().unwrap_or(&rel);
+            glob_matches(&self.pattern, name)
+        }
+    }
+}
+
+/// The longest of the watched paths that contains the given path.
+pub(crate) fn root_of<'a>(path: &Path, roots: &'a [PathBuf]) -> Option<&'a Path> {
+    roots
+        .iter()
+        .filter(|root| path.starts_with(root))
+        .max_by_key(|root| root.components().count())
+        .map(|root| root.as_path())
+}
+
+/// The path relative to the watched path that contains it, with `/` separators.
+///
+/// If the path is itself a watched path, or is not within any, the file name is used.
+fn relative_path(path: &Path, roots: &[PathBuf]) -> String {
+    let rel = root_of(path, roots).and_then(|root| path.strip_prefix(root).ok());
+    match rel {
+        Some(rel) if rel.components().next().is_some() => slash_path(rel),
+        _ => path
+            .file_name()
+            .map(|name| name.to_string_lossy().into_owned())
+            .unwrap_or_default(),
+    }
+}
+
+/// Join the normal components of the path with `/`.
+fn slash_path(path: &Path) -> String {
+    let names: Vec<_> = path
+        .components()
+        .filter_map(|c| match c {
+            Component::Normal(name) => Some(name.to_string_lossy()),
+            _ => None,
+        })
+        .collect();
+    names.join("/")
+}
+
+/// Whether or not the given path is ignored by the ignore files within the watched directory.
+///
+/// Patterns of ignore files within deeper directories take precedence, and the last matching
+/// pattern within a file wins. Everything beneath an ignored directory is ignored.
+fn is_ignored(root: &Path, path: &Path, is_dir: bool, ignores: &IgnoreCache) -> bool {
+    let rel = match path.strip_prefix(root) {
+        Ok(rel) => rel,
+        Err(_) => return false,
+    };
+    let names: Vec<_> = rel.components().collect();
+    let mut rules = vec![];
+    let mut dir = root.to_path_buf();
+    for (ix, name) in names.iter().enumerate() {
+        rules.push(ignores.rules(&dir));
+        let child = dir.join(name);
+        let child_is_dir = ix + 1 < names.len() || is_dir;
+        let last_match = rules
+            .iter()
+            .flat_map(|rules| rules.iter())
+            .rev()
+            .find(|r| r.matches(&child, child_is_dir));
+        if last_match.is_some_and(|r| !r.negated) {
+            return true;
+        }
+        dir = child;
+    }
+    false
+}
+
+/// Read the rules of all ignore files within the given directory.
+fn read_ignore_files(dir: &Path) -> Vec<IgnoreRule> {
+    let mut rules = vec![];
+    for name in IGNORE_FILES {
+        if let Ok(contents) = std::fs::read_to_string(dir.join(name)) {
+            rules.extend(contents.lines().filter_map(|l| IgnoreRule::parse(dir, l)));
+        }
+    }
+    rules
+}
+
+/// Whether or not the given glob pattern matches the given `/` separated path.
+fn glob_matches(pattern: &str, path: &str) -> bool {
+    let pattern: Vec<char> = pattern.chars().collect();
+    let path: Vec<char> = path.chars().collect();
+    glob_matches_chars(&pattern, &path)
+}
+
+fn glob_matches_chars(pattern: &[char], s: &[char]) -> bool {
+    match *pattern {
+        [] => s.is_empty(),
+        ['*', '*', ref rest @ ..] => {
+            // `**/` may also match zero directories.
+            if let ['/', ref after @ ..] = *rest {
+                if glob_matches_chars(after, s) {
+                    return true;
+                }
+            }
+            (0..=s.len()).any(|ix| glob_matches_chars(rest, &s[ix..]))
+        }
+        ['*', ref rest @ ..] => {
+            let component_len = s.iter().position(|&c| c == '/').unwrap_or(s.len());
+            (0..=component_len).any(|ix| glob_matches_chars(rest, &s[ix..]))
+        }
+        ['?', ref rest @ ..] => match *s {
+            [c, ref s @ ..] if c != '/' => glob_matches_chars(rest, s),
+            _ => false,
+        },
+        ['[', ref rest @ ..] => match rest.iter().skip(1).position(|&c| c == ']') {
+            Some(end) => {
+                let (class, rest) = (&rest[..end + 1], &rest[end + 2..]);
+                match *s {
+                    [c, ref s @ ..] if c != '/' && class_matches(class, c) => {
+                        glob_matches_chars(rest, s)
+                    }
+                    _ => false,
+                }
+            }
+            None => s.first() == Some(&'[') && glob_matches_chars(rest, &s[1..]),
+        },
+        ['\\', c, ref rest @ ..] | [c, ref rest @ ..] => {
+            s.first() == Some(&c) && glob_matches_chars(rest, &s[1..])
+        }
+    }
+}
+
+/// Whether or not the character is within the given `[...]` class, excluding the brackets.
+fn class_matches(class: &[char], c: char) -> bool {
+    let (negated, class) = match *class {
+        ['!', ref rest @ ..] | ['^', ref rest @ ..] if !rest.is_empty() => (true, rest),
+        _ => (false, class),
+    };
+    let mut ix = 0;
+    let mut found = false;
+    while ix < class.len() {
+        if ix + 2 < class.len() && class[ix + 1] == '-' {
+            found |= class[ix] <= c && c <= class[ix + 2];
+            ix += 3;
+        } else {
+            found |= class[ix] == c;
+            ix += 1;
+        }
+    }
+    found != negated
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Whether or not the rules parsed from the given ignore file lines ignore the path, relative
+    /// to the directory containing the ignore file.
+    fn ignores(lines: &[&str], path: &str, is_dir: bool) -> bool {
+        let dir = Path::new("/root");
+        let rules: Vec<_> = lines
+            .iter()
+            .filter_map(|l| IgnoreRule::parse(dir, l))
+            .collect();
+        let path = dir.join(path);
+        let last_match = rules.iter().rev().find(|r| r.matches(&path, is_dir));
+        last_match.is_some_and(|r| !r.negated)
+    }
+
+    #[test]
+    fn star_does_not_match_across_separators() {
+        assert!(glob_matches("*.frag", "a.frag"));
+        assert!(!glob_matches("*.frag", "dir/a.frag"));
+        assert!(glob_matches("dir/*.frag", "dir/a.frag"));
+        assert!(!glob_matches("dir/*", "dir/sub/a.frag"));
+    }
+
+    #[test]
+    fn double_star_matches_any_number_of_directories() {
+        assert!(glob_matches("**/a.frag", "a.frag"));
+        assert!(glob_matches("**/a.frag", "x/y/a.frag"));
+        assert!(glob_matches("src/**/*.frag", "src/a.frag"));
+        assert!(glob_matches("src/**/*.frag", "src/x/y/a.frag"));
+        assert!(!glob_matches("src/**/*.frag", "other/a.frag"));
+    }
+
+    #[test]
+    fn question_mark_and_character_classes() {
+        assert!(glob_matches("?.frag", "a.frag"));
+        assert!(!glob_matches("?.frag", "ab.frag"));
+        assert!(glob_matches("[a-c].frag", "b.frag"));
+        assert!(!glob_matches("[a-c].frag", "d.frag"));
+        assert!(glob_matches("[!x].frag", "a.frag"));
+        assert!(!glob_matches("[!x].frag", "x.frag"));
+        assert!(glob_matches("[^x].frag", "a.frag"));
+        assert!(!glob_matches("[a-z].frag", "/.frag"));
+        assert!(glob_matches("\\[a\\].frag", "[a].frag"));
+    }
+
+    #[test]
+    fn ignore_rules_negation() {
+        let lines = ["*.frag", "!keep.frag"];
+        assert!(ignores(&lines, "a.frag", false));
+        assert!(!ignores(&lines, "keep.frag", false));
+        assert!(!ignores(&lines, "a.vert", false));
+    }
+
+    #[test]
+    fn ignore_rules_anchored() {
+        let lines = ["/top.frag", "gen/*.frag"];
+        assert!(ignores(&lines, "top.frag", false));
+        assert!(!ignores(&lines, "sub/top.frag", false));
+        assert!(ignores(&lines, "gen/a.frag", false));
+        assert!(!ignores(&lines, "sub/gen/a.frag", false));
+    }
+
+    #[test]
+    fn ignore_rules_dir_only_and_comments() {
+        let lines = ["# comment", "", "build/"];
+        assert!(ignores(&lines, "build", true));
+        assert!(!ignores(&lines, "build", false));
+        assert!(!ignores(&lines, "# comment", false));
+    }
+
+    #[test]
+    fn ignore_files_are_cached_and_invalidated() {
+        let root = crate::common::TempDir::new("ignore-cache");
+        let sub = root.join("sub");
+        std::fs::create_dir_all(&sub).unwrap();
+        let gitignore = sub.join(".gitignore");
+        std::fs::write(&gitignore, "a.frag\n").unwrap();
+        let roots = vec![root.to_path_buf()];
+        let filter = FileFilter::new().ignore_files(true);
+        let cache = IgnoreCache::default();
+        let shader = sub.join("a.frag");
+        assert!(!filter.is_shader(&shader, &roots, &cache));
+        assert!(filter.is_shader(&root.join("a.frag"), &roots, &cache));
+
+        // Until invalidated, the cached rules are used.
+        std::fs::write(&gitignore, "b.frag\n").unwrap();
+        assert!(!filter.is_shader(&shader, &roots, &cache));
+        cache.invalidate(std::slice::from_ref(&gitignore));
+        assert!(filter.is_shader(&shader, &roots, &cache));
+
+        // Removing the directory forgets the rules of everything beneath it.
+        std::fs::write(&gitignore, "a.frag\n").unwrap();
+        cache.invalidate(std::slice::from_ref(&sub));
+        assert!(!filter.is_shader(&shader, &roots, &cache));
+    }
+}
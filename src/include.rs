@@ -0,0 +1,983 @@
+//! A small `#include` preprocessing pass that runs before GLSL is handed to naga.
+//!
+//! naga's GLSL frontend has no notion of include resolution, so `#include "file"` and
+//! `#include <file>` directives are expanded textually here. Quoted includes are first looked up
+//! relative to the including file and then within the include directories, while angle-bracket
+//! includes are only looked up within the include directories. A file containing `#pragma once` is
+//! only expanded the first time it is included.
+//!
+//! Includes within block comments and within conditional blocks that are known not to be compiled,
+//! e.g. `#if 0` or `#ifdef X` where `X` is not defined by the options, are skipped. Only integer
+//! literals and `defined` checks are evaluated, so whether some blocks are compiled is left to
+//! naga. An include that cannot be expanded within such a block is replaced by an `#error`
+//! directive, so that it is only reported if naga compiles the block.
+
+use std::collections::{BTreeMap, HashMap, HashSet};
+use std::path::{Component, Path, PathBuf};
+use thiserror::Error;
+
+/// Errors that might occur while expanding `#include` directives.
+///
+/// The path of a directive is `None` if it is within a string passed to `preprocess_str`.
+#[derive(Debug, Error)]
+pub enum PreprocessError {
+    #[error("failed to read {path:?}: {err}")]
+    Io {
+        path: PathBuf,
+        #[source]
+        err: std::io::Error,
+    },
+    #[error("{}:{line}: malformed `#include` directive", source_name(.path))]
+    Malformed { path: Option<PathBuf>, line: usize },
+    #[error("{}:{line}: could not find included file {include:?}", source_name(.path))]
+    NotFound {
+        path: Option<PathBuf>,
+        line: usize,
+        include: String,
+    },
+    #[error("{path:?} is included recursively by itself")]
+    Cycle { path: PathBuf },
+}
+
+/// GLSL source with all `#include` directives expanded.
+#[derive(Clone, Debug)]
+pub struct Preprocessed {
+    /// The expanded source, ready to be handed to naga.
+    pub source: String,
+    /// Every file that was included while expanding the source, in the order in which they were
+    /// first encountered.
+    pub dependencies: Vec<PathBuf>,
+    /// The file from which each expanded source was read, indexed by `line_origins`.
+    files: Vec<Option<PathBuf>>,
+    /// The index into `files` and the 1-based line number of each line of the expanded source.
+    line_origins: Vec<(usize, usize)>,
+    /// Includes that could not be expanded within conditional blocks that may not be compiled.
+    deferred: Vec<Deferred>,
+}
+
+/// An include replaced by an `#error` directive at the given 0-based line of the expanded source.
+#[derive(Debug)]
+struct Deferred {
+    line: usize,
+    err: PreprocessError,
+}
+
+impl Preprocessed {
+    /// The path of the top-level file, or `None` if the source was passed to `preprocess_str`.
+    pub fn path(&self) -> Option<&Path> {
+        self.files.first().and_then(|file| file.as_deref())
+    }
+
+    /// Source that is used as-is, e.g. WGSL, which has no `#include` directives to expand.
+    #[cfg(any(feature = "wgsl-in", feature = "spv-in"))]
+    pub(crate) fn verbatim(source: String, path: Option<&Path>) -> Self {
+        let line_origins = (0..source.lines().count().max(1))
+            .map(|line| (0, line + 1))
+            .collect();
+        Preprocessed {
+            source,
+            dependencies: vec![],
+            files: vec![path.map(Path::to_path_buf)],
+            line_origins,
+            deferred: vec![],
+        }
+    }
+
+    /// The file and 1-based line number from which the given 0-based line of the expanded source
+    /// originated.
+    ///
+    /// The file is `None` for lines originating from a string passed to `preprocess_str`.
+    pub fn line_origin(&self, line: usize) -> Option<(Option<&Path>, usize)> {
+        self.line_origins
+            .get(line)
+            .map(|&(file, line)| (self.files[file].as_deref(), line))
+    }
+
+    /// The error of the include that was deferred to the line at the given byte offset of the
+    /// expanded source, if any.
+    ///
+    /// Used to report the original error when naga compiles the `#error` directive replacing it.
+    pub(crate) fn deferred_error(&self, offset: usize) -> Option<PreprocessError> {
+        let offset = offset.min(self.source.len());
+        let line = self.source.as_bytes()[..offset]
+            .iter()
+            .filter(|&&b| b == b'\n')
+            .count();
+        self.deferred
+            .iter()
+            .find(|deferred| deferred.line == line)
+            .map(|deferred| deferred.err.duplicate())
+    }
+}
+
+impl PreprocessError {
+    /// A copy of the error. I/O errors cannot be cloned, so only their kind and message are kept.
+    fn duplicate(&self) -> Self {
+        match *self {
+            PreprocessError::Io { ref path, ref err } => PreprocessError::Io {
+                path: path.clone(),
+                err: std::io::Error::new(err.kind(), err.to_string()),
+            },
+            PreprocessError::Malformed { ref path, line } => PreprocessError::Malformed {
+                path: path.clone(),
+                line,
+            },
+            PreprocessError::NotFound {
+                ref path,
+                line,
+                ref include,
+            } => PreprocessError::NotFound {
+                path: path.clone(),
+                line,
+                include: include.clone(),
+            },
+            PreprocessError::Cycle { ref path } => PreprocessError::Cycle { path: path.clone() },
+        }
+    }
+}
+
+impl Clone for Deferred {
+    fn clone(&self) -> Self {
+        Deferred {
+            line: self.line,
+            err: self.err.duplicate(),
+        }
+    }
+}
+
+/// Tracks which files each top-level shader transitively includes.
+///
+/// Used by the `Watch` to determine which shaders need recompiling when an included file changes.
+#[derive(Debug, Default)]
+pub(crate) struct DependencyGraph {
+    includes: HashMap<PathBuf, HashSet<PathBuf>>,
+}
+
+/// A preprocessor directive that we care about.
+enum Directive<'a> {
+    Include { name: &'a str, angled: bool },
+    PragmaOnce,
+    Malformed,
+    If(&'a str),
+    Ifdef(&'a str),
+    Ifndef(&'a str),
+    Elif(&'a str),
+    Else,
+    Endif,
+    Define(&'a str),
+    Undef(&'a str),
+}
+
+/// Whether the lines within a conditional block are compiled.
+#[derive(Clone, Copy, Debug, PartialEq)]
+enum Branch {
+    Active,
+    Inactive,
+    /// Depends on macros whose definitions are unknown, or on a condition that is not evaluated.
+    Unknown,
+}
+
+/// A conditional block opened by `#if`, `#ifdef` or `#ifndef`.
+#[derive(Clone, Copy, Debug)]
+struct Block {
+    /// Whether the lines of the current branch are compiled.
+    current: Branch,
+    /// Whether any branch up to and including the current one is compiled.
+    taken: Branch,
+}
+
+/// The conditional blocks enclosing the line being scanned.
+#[derive(Debug, Default)]
+struct Conditionals {
+    /// Whether each macro is known to be defined. Macros missing from the map may or may not be,
+    /// e.g. those predefined by naga.
+    defines: HashMap<String, bool>,
+    blocks: Vec<Block>,
+}
+
+/// State shared throughout the expansion of a single top-level source.
+struct Expansion<'a> {
+    include_dirs: &'a [PathBuf],
+    stack: Vec<PathBuf>,
+    once: HashSet<PathBuf>,
+    dependencies: Vec<PathBuf>,
+    source: String,
+    files: Vec<Option<PathBuf>>,
+    line_origins: Vec<(usize, usize)>,
+    deferred: Vec<Deferred>,
+    conditionals: Conditionals,
+}
+
+impl DependencyGraph {
+    /// Re-scan the includes of the given shader file.
+    ///
+    /// Scanning is lenient: includes that cannot be resolved or read are skipped so that the graph
+    /// stays useful while a shader is being edited. If the shader no longer exists it is removed.
+    pub(crate) fn update(&mut self, shader: &Path, include_dirs: &[PathBuf]) {
+        if !shader.is_file() {
+            self.includes.remove(shader);
+            return;
+        }
+        let mut includes = HashSet::new();
+        let mut conditionals = Conditionals::default();
+        collect_dependencies(shader, include_dirs, &mut conditionals, &mut includes);
+        self.includes.insert(shader.to_path_buf(), includes);
+    }
+
+    /// Re-scan the includes of all known shaders, e.g. after the include directories changed.
+    pub(crate) fn rescan(&mut self, include_dirs: &[PathBuf]) {
+        let shaders: Vec<PathBuf> = self.includes.keys().cloned().collect();
+        for shader in &shaders {
+            self.update(shader, include_dirs);
+        }
+    }
+
+    /// Forget all shaders for which the given predicate returns `false`.
+    pub(crate) fn retain<F>(&mut self, mut f: F)
+    where
+        F: FnMut(&Path) -> bool,
+    {
+        self.includes.retain(|shader, _| f(shader));
+    }
+
+    /// All known shaders that transitively include the file at the given path.
+    pub(crate) fn dependents<'a>(&'a self, path: &Path) -> impl 'a + Iterator<Item = &'a PathBuf> {
+        let path = normalize(path);
+        self.includes
+            .iter()
+            .filter(move |(_, includes)| includes.contains(&path))
+            .map(|(shader, _)| shader)
+    }
+}
+
+impl Branch {
+    fn not(self) -> Self {
+        match self {
+            Branch::Active => Branch::Inactive,
+            Branch::Inactive => Branch::Active,
+            Branch::Unknown => Branch::Unknown,
+        }
+    }
+
+    /// Whether a line within both this and the given branch is compiled.
+    fn and(self, other: Self) -> Self {
+        match (self, other) {
+            (Branch::Inactive, _) | (_, Branch::Inactive) => Branch::Inactive,
+            (Branch::Unknown, _) | (_, Branch::Unknown) => Branch::Unknown,
+            _ => Branch::Active,
+        }
+    }
+}
+
+impl Block {
+    fn new(branch: Branch) -> Self {
+        Block {
+            current: branch,
+            taken: branch,
+        }
+    }
+
+    /// Move on to a branch with the given condition, as for `#elif` or, if `Active`, `#else`.
+    fn next(&mut self, branch: Branch) {
+        *self = match self.taken {
+            Branch::Active => Block {
+                current: Branch::Inactive,
+                taken: Branch::Active,
+            },
+            Branch::Inactive => Block::new(branch),
+            Branch::Unknown => Block {
+                current: branch.and(Branch::Unknown),
+                taken: match branch {
+                    Branch::Active => Branch::Active,
+                    _ => Branch::Unknown,
+                },
+            },
+        };
+    }
+}
+
+impl Conditionals {
+    /// Conditionals for a source compiled with the given macros defined.
+    fn new(defines: &BTreeMap<String, String>) -> Self {
+        let defines = defines.keys().map(|name| (name.clone(), true)).collect();
+        Conditionals {
+            defines,
+            blocks: vec![],
+        }
+    }
+
+    /// Whether the line being scanned is compiled.
+    fn branch(&self) -> Branch {
+        self.blocks
+            .iter()
+            .fold(Branch::Active, |branch, block| branch.and(block.current))
+    }
+
+    /// Update the enclosing blocks and known macros for the given directive.
+    fn apply(&mut self, directive: &Directive) {
+        match *directive {
+            Directive::If(condition) => {
+                let branch = self.evaluate(condition);
+                self.blocks.push(Block::new(branch));
+            }
+            Directive::Ifdef(name) => {
+                let branch = self.defined(name);
+                self.blocks.push(Block::new(branch));
+            }
+            Directive::Ifndef(name) => {
+                let branch = self.defined(name).not();
+                self.blocks.push(Block::new(branch));
+            }
+            Directive::Elif(condition) => {
+                let branch = self.evaluate(condition);
+                if let Some(block) = self.blocks.last_mut() {
+                    block.next(branch);
+                }
+            }
+            Directive::Else => {
+                if let Some(block) = self.blocks.last_mut() {
+                    block.next(Branch::Active);
+                }
+            }
+            Directive::Endif => {
+                self.blocks.pop();
+            }
+            Directive::Define(name) | Directive::Undef(name) => {
+                let defined = matches!(*directive, Directive::Define(_));
+                match self.branch() {
+                    Branch::Active => {
+                        self.defines.insert(name.to_string(), defined);
+                    }
+                    Branch::Inactive => (),
+                    Branch::Unknown => {
+                        self.defines.remove(name);
+                    }
+                }
+            }
+            _ => (),
+        }
+    }
+
+    fn defined(&self, name: &str) -> Branch {
+        match self.defines.get(name) {
+            Some(true) => Branch::Active,
+            Some(false) => Branch::Inactive,
+            None => Branch::Unknown,
+        }
+    }
+
+    /// Evaluate the condition of an `#if` or `#elif` directive.
+    ///
+    /// Only integer literals and `defined` checks, optionally negated, are understood.
+    fn evaluate(&self, condition: &str) -> Branch {
+        let end = [condition.find("//"), condition.find("/*")]
+            .iter()
+            .flatten()
+            .min()
+            .copied()
+            .unwrap_or(condition.len());
+        let condition = condition[..end].trim();
+        if let Some(rest) = condition.strip_prefix('!') {
+            return self.evaluate(rest).not();
+        }
+        if let Some(rest) = condition.strip_prefix("defined") {
+            let rest = rest.trim();
+            let name = rest
+                .strip_prefix('(')
+                .and_then(|rest| rest.strip_suffix(')'))
+                .unwrap_or(rest)
+                .trim();
+            return match identifier(name) {
+                Some(ident) if ident == name => self.defined(name),
+                _ => Branch::Unknown,
+            };
+        }
+        match condition.parse::<i64>() {
+            Ok(0) => Branch::Inactive,
+            Ok(_) => Branch::Active,
+            Err(_) => Branch::Unknown,
+        }
+    }
+}
+
+impl<'a> Expansion<'a> {
+    fn new(include_dirs: &'a [PathBuf], defines: &BTreeMap<String, String>) -> Self {
+        Expansion {
+            include_dirs,
+            stack: vec![],
+            once: HashSet::new(),
+            dependencies: vec![],
+            source: String::new(),
+            files: vec![],
+            line_origins: vec![],
+            deferred: vec![],
+            conditionals: Conditionals::new(defines),
+        }
+    }
+
+    fn finish(self) -> Preprocessed {
+        Preprocessed {
+            source: self.source,
+            dependencies: self.dependencies,
+            files: self.files,
+            line_origins: self.line_origins,
+            deferred: self.deferred,
+        }
+    }
+
+    fn push_line(&mut self, line: &str, file: usize, ix: usize) {
+        self.source.push_str(line);
+        self.source.push('\n');
+        self.line_origins.push((file, ix + 1));
+    }
+
+    /// Fail with the given error, unless the include is within a block that may not be compiled,
+    /// in which case it is replaced by an `#error` directive.
+    fn fail(
+        &mut self,
+        err: PreprocessError,
+        branch: Branch,
+        file: usize,
+        ix: usize,
+    ) -> Result<(), PreprocessError> {
+        if branch != Branch::Unknown {
+            return Err(err);
+        }
+        let line = self.line_origins.len();
+        self.deferred.push(Deferred { line, err });
+        self.push_line("#error unresolved include", file, ix);
+        Ok(())
+    }
+
+    /// Expand the given source, appending the result to `self.source`.
+    ///
+    /// `path` is the file the source was read from, if any. `dir` is the directory against which
+    /// quoted includes are resolved first.
+    fn expand(
+        &mut self,
+        source: &str,
+        path: Option<&Path>,
+        dir: Option<&Path>,
+    ) -> Result<(), PreprocessError> {
+        let err_path = || path.map(Path::to_path_buf);
+        let file = self.files.len();
+        self.files.push(path.map(Path::to_path_buf));
+        let mut in_comment = false;
+        for (ix, line) in source.lines().enumerate() {
+            let directive = if in_comment {
+                None
+            } else {
+                parse_directive(line)
+            };
+            in_comment = ends_in_comment(line, in_comment);
+            let branch = self.conditionals.branch();
+            let include = match directive {
+                Some(Directive::Include { name, angled }) => Some((name, angled)),
+                Some(Directive::Malformed) => None,
+                Some(Directive::PragmaOnce) => {
+                    match path {
+                        Some(path) if branch != Branch::Inactive => {
+                            self.once.insert(path.to_path_buf());
+                        }
+                        _ => (),
+                    }
+                    continue;
+                }
+                directive => {
+                    if let Some(ref directive) = directive {
+                        self.conditionals.apply(directive);
+                    }
+                    self.push_line(line, file, ix);
+                    continue;
+                }
+            };
+            if branch == Branch::Inactive {
+                continue;
+            }
+            let (name, angled) = match include {
+                Some(include) => include,
+                None => {
+                    let path = err_path();
+                    let err = PreprocessError::Malformed { path, line: ix + 1 };
+                    self.fail(err, branch, file, ix)?;
+                    continue;
+                }
+            };
+            let include_path = match resolve(name, angled, dir, self.include_dirs) {
+                Some(p) => p,
+                None => {
+                    let path = err_path();
+                    let include = name.to_string();
+                    let line = ix + 1;
+                    let err = PreprocessError::NotFound {
+                        path,
+                        line,
+                        include,
+                    };
+                    self.fail(err, branch, file, ix)?;
+                    continue;
+                }
+            };
+            if self.once.contains(&include_path) {
+                continue;
+            }
+            if self.stack.contains(&include_path) {
+                // Within a block that may not be compiled, assume the recursion is broken by an
+                // include guard.
+                if branch == Branch::Unknown {
+                    continue;
+                }
+                return Err(PreprocessError::Cycle { path: include_path });
+            }
+            let include_src = read(&include_path)?;
+            if !self.dependencies.contains(&include_path) {
+                self.dependencies.push(include_path.clone());
+            }
+            self.stack.push(include_path.clone());
+            self.expand(&include_src, Some(&include_path), include_path.parent())?;
+            self.stack.pop();
+        }
+        Ok(())
+    }
+}
+
+/// Read the file at the given path and expand all `#include` directives within it.
+///
+/// Quoted includes are first resolved relative to the file's directory, then against each of the
+/// given `include_dirs` in order.
+pub fn preprocess_file(
+    path: &Path,
+    include_dirs: &[PathBuf],
+) -> Result<Preprocessed, PreprocessError> {
+    let source = read(&normalize(path))?;
+    preprocess_file_source(path, &source, include_dirs, &BTreeMap::new())
+}
+
+/// Expand all `#include` directives within the given source, read from the file at the given path.
+///
+/// This allows for reporting failures to read the top-level file separately from its includes.
+/// `defines` are the macros defined by the options the source is compiled with.
+pub(crate) fn preprocess_file_source(
+    path: &Path,
+    source: &str,
+    include_dirs: &[PathBuf],
+    defines: &BTreeMap<String, String>,
+) -> Result<Preprocessed, PreprocessError> {
+    let path = normalize(path);
+    let mut expansion = Expansion::new(include_dirs, defines);
+    expansion.stack.push(path.clone());
+    expansion.expand(source, Some(&path), path.parent())?;
+    Ok(expansion.finish())
+}
+
+/// Expand all `#include` directives within the given source string.
+///
+/// Quoted includes are first resolved relative to `dir` if one is given, then against each of the
+/// given `include_dirs` in order.
+pub fn preprocess_str(
+    source: &str,
+    dir: Option<&Path>,
+    include_dirs: &[PathBuf],
+) -> Result<Preprocessed, PreprocessError> {
+    preprocess_str_with_defines(source, dir, include_dirs, &BTreeMap::new())
+}
+
+/// Expand all `#include` directives within the given source string, compiled with the given
+/// macros defined.
+pub(crate) fn preprocess_str_with_defines(
+    source: &str,
+    dir: Option<&Path>,
+    include_dirs: &[PathBuf],
+    defines: &BTreeMap<String, String>,
+) -> Result<Preprocessed, PreprocessError> {
+    let mut expansion = Expansion::new(include_dirs, defines);
+    expansion.expand(source, None, dir)?;
+    Ok(expansion.finish())
+}
+
+/// The name of the source with the given path in error messages.
+fn source_name(path: &Option<PathBuf>) -> String {
+    match *path {
+        Some(ref path) => format!("{:?}", path),
+        None => "<string>".to_string(),
+    }
+}
+
+/// Lexically normalize the path by removing `.` components and resolving `..` where possible.
+///
+/// This avoids hitting the file system so that paths of files that have since been removed still
+/// compare equal to the paths reported by notify.
+pub(crate) fn normalize(path: &Path) -> PathBuf {
+    let mut normalized = PathBuf::new();
+    for component in path.components() {
+        match component {
+            Component::CurDir => (),
+            Component::ParentDir => match normalized.components().next_back() {
+                Some(Component::Normal(_)) => {
+                    normalized.pop();
+                }
+                _ => normalized.push(component),
+            },
+            _ => normalized.push(component),
+        }
+    }
+    normalized
+}
+
+fn read(path: &Path) -> Result<String, PreprocessError> {
+    std::fs::read_to_string(path).map_err(|err| PreprocessError::Io {
+        path: path.to_path_buf(),
+        err,
+    })
+}
+
+/// Find the file referred to by an `#include` directive.
+fn resolve(
+    name: &str,
+    angled: bool,
+    dir: Option<&Path>,
+    include_dirs: &[PathBuf],
+) -> Option<PathBuf> {
+    let local = dir.filter(|_| !angled).map(|dir| dir.join(name));
+    local
+        .into_iter()
+        .chain(include_dirs.iter().map(|dir| dir.join(name)))
+        .map(|p| normalize(&p))
+        .find(|p| p.is_file())
+}
+
+/// Leniently collect every file transitively included by the file at `path`.
+///
+/// Includes within block comments and blocks known not to be compiled are skipped.
+fn collect_dependencies(
+    path: &Path,
+    include_dirs: &[PathBuf],
+    conditionals: &mut Conditionals,
+    deps: &mut HashSet<PathBuf>,
+) {
+    let source = match std::fs::read_to_string(path) {
+        Ok(source) => source,
+        Err(_) => return,
+    };
+    let mut in_comment = false;
+    for line in source.lines() {
+        let directive = if in_comment {
+            None
+        } else {
+            parse_directive(line)
+        };
+        in_comment = ends_in_comment(line, in_comment);
+        match directive {
+            Some(Directive::Include { name, angled }) => {
+                if conditionals.branch() == Branch::Inactive {
+                    continue;
+                }
+                if let Some(include_path) = resolve(name, angled, path.parent(), include_dirs) {
+                    if deps.insert(include_path.clone()) {
+                        collect_dependencies(&include_path, include_dirs, conditionals, deps);
+                    }
+                }
+            }
+            Some(ref directive) => conditionals.apply(directive),
+            None => (),
+        }
+    }
+}
+
+/// Whether a block comment is open at the end of the given line, given whether one was open at its
+/// start.
+fn ends_in_comment(line: &str, mut in_comment: bool) -> bool {
+    let mut rest = line;
+    loop {
+        if in_comment {
+            match rest.find("*/") {
+                Some(end) => {
+                    rest = &rest[end + 2..];
+                    in_comment = false;
+                }
+                None => return true,
+            }
+        } else {
+            let line_comment = rest.find("//");
+            match rest.find("/*") {
+                Some(start) if !matches!(line_comment, Some(ix) if ix < start) => {
+                    rest = &rest[start + 2..];
+                    in_comment = true;
+                }
+                _ => return false,
+            }
+        }
+    }
+}
+
+/// The identifier at the start of the given string, if any.
+fn identifier(s: &str) -> Option<&str> {
+    let end = s
+        .find(|c: char| !(c.is_alphanumeric() || c == '_'))
+        .unwrap_or(s.len());
+    Some(&s[..end]).filter(|ident| !ident.is_empty())
+}
+
+/// Parse an `#include`, `#pragma once` or conditional directive from the given line.
+fn parse_directive(line: &str) -> Option<Directive<'_>> {
+    let rest = line.trim_start().strip_prefix('#')?.trim_start();
+    let keyword = identifier(rest)?;
+    let rest = &rest[keyword.len()..];
+    let name = || identifier(rest.trim_start()).unwrap_or_default();
+    match keyword {
+        "pragma" => {
+            return match rest.trim() {
+                "once" => Some(Directive::PragmaOnce),
+                _ => None,
+            }
+        }
+        "include" => (),
+        "if" => return Some(Directive::If(rest)),
+        "ifdef" => return Some(Directive::Ifdef(name())),
+        "ifndef" => return Some(Directive::Ifndef(name())),
+        "elif" => return Some(Directive::Elif(rest)),
+        "else" => return Some(Directive::Else),
+        "endif" => return Some(Directive::Endif),
+        "define" => return Some(Directive::Define(name())),
+        "undef" => return Some(Directive::Undef(name())),
+        _ => return None,
+    }
+    let rest = rest.trim();
+    let (close, angled) = match rest.chars().next() {
+        Some('"') => ('"', false),
+        Some('<') => ('>', true),
+        _ => return Some(Directive::Malformed),
+    };
+    let rest = &rest[1..];
+    match rest.find(close) {
+        Some(end) if end > 0 => {
+            let trailing = rest[end + 1..].trim();
+            let comment = trailing.starts_with("//") || trailing.starts_with("/*");
+            if !trailing.is_empty() && !comment {
+                return Some(Directive::Malformed);
+            }
+            let name = &rest[..end];
+            Some(Directive::Include { name, angled })
+        }
+        _ => Some(Directive::Malformed),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::common::TempDir;
+    use std::fs;
+
+    /// A directory unique to the given test containing the given files.
+    fn files(name: &str, files: &[(&str, &str)]) -> TempDir {
+        let dir = TempDir::new(&format!("include-{}", name));
+        for (path, contents) in files {
+            let path = dir.join(path);
+            fs::create_dir_all(path.parent().unwrap()).unwrap();
+            fs::write(path, contents).unwrap();
+        }
+        dir
+    }
+
+    #[test]
+    fn pragma_once_expands_a_file_once() {
+        let dir = files(
+            "once",
+            &[
+                (
+                    "main.frag",
+                    "#include \"a.glsl\"\n#include \"a.glsl\"\nmain\n",
+                ),
+                ("a.glsl", "#pragma once\na\n"),
+            ],
+        );
+        let pp = preprocess_file(&dir.join("main.frag"), &[]).unwrap();
+        assert_eq!(pp.source, "a\nmain\n");
+        assert_eq!(pp.dependencies, vec![dir.join("a.glsl")]);
+    }
+
+    #[test]
+    fn repeated_includes_without_pragma_once_are_expanded_each_time() {
+        let dir = files(
+            "repeated",
+            &[
+                ("main.frag", "#include \"a.glsl\"\n#include \"a.glsl\"\n"),
+                ("a.glsl", "a\n"),
+            ],
+        );
+        let pp = preprocess_file(&dir.join("main.frag"), &[]).unwrap();
+        assert_eq!(pp.source, "a\na\n");
+    }
+
+    #[test]
+    fn cycles_are_reported() {
+        let dir = files(
+            "cycle",
+            &[
+                ("main.frag", "#include \"a.glsl\"\n"),
+                ("a.glsl", "#include \"b.glsl\"\n"),
+                ("b.glsl", "#include \"a.glsl\"\n"),
+            ],
+        );
+        match preprocess_file(&dir.join("main.frag"), &[]) {
+            Err(PreprocessError::Cycle { path }) => assert_eq!(path, dir.join("a.glsl")),
+            other => panic!("expected a cycle, got {:?}", other),
+        }
+    }
+
+    #[test]
+    fn quoted_includes_prefer_the_local_directory() {
+        let dir = files(
+            "lookup",
+            &[
+                (
+                    "shaders/main.frag",
+                    "#include \"a.glsl\"\n#include <a.glsl>\n",
+                ),
+                ("shaders/a.glsl", "local\n"),
+                ("include/a.glsl", "include dir\n"),
+            ],
+        );
+        let include_dirs = [dir.join("include")];
+        let pp = preprocess_file(&dir.join("shaders/main.frag"), &include_dirs).unwrap();
+        assert_eq!(pp.source, "local\ninclude dir\n");
+    }
+
+    #[test]
+    fn angled_includes_ignore_the_local_directory() {
+        let dir = files(
+            "angled",
+            &[
+                ("main.frag", "void main() {}\n#include <a.glsl>\n"),
+                ("a.glsl", "a\n"),
+            ],
+        );
+        match preprocess_file(&dir.join("main.frag"), &[]) {
+            Err(PreprocessError::NotFound {
+                path,
+                line,
+                include,
+            }) => {
+                assert_eq!(path, Some(dir.join("main.frag")));
+                assert_eq!(line, 2);
+                assert_eq!(include, "a.glsl");
+            }
+            other => panic!("expected a missing include, got {:?}", other),
+        }
+    }
+
+    #[test]
+    fn line_origins_map_back_to_each_file() {
+        let dir = files(
+            "origins",
+            &[
+                ("main.frag", "one\n#include \"a.glsl\"\nthree\n"),
+                ("a.glsl", "a1\na2\n"),
+            ],
+        );
+        let main = dir.join("main.frag");
+        let a = dir.join("a.glsl");
+        let pp = preprocess_file(&main, &[]).unwrap();
+        assert_eq!(pp.source, "one\na1\na2\nthree\n");
+        assert_eq!(pp.path(), Some(main.as_path()));
+        assert_eq!(pp.line_origin(0), Some((Some(main.as_path()), 1)));
+        assert_eq!(pp.line_origin(1), Some((Some(a.as_path()), 1)));
+        assert_eq!(pp.line_origin(2), Some((Some(a.as_path()), 2)));
+        assert_eq!(pp.line_origin(3), Some((Some(main.as_path()), 3)));
+        assert_eq!(pp.line_origin(4), None);
+    }
+
+    #[test]
+    fn malformed_directives_are_reported() {
+        let pp = preprocess_str("ok\n#include nope\n", None, &[]);
+        assert!(matches!(
+            pp,
+            Err(PreprocessError::Malformed {
+                path: None,
+                line: 2
+            })
+        ));
+        let err = pp.unwrap_err();
+        assert_eq!(
+            err.to_string(),
+            "<string>:2: malformed `#include` directive"
+        );
+        assert!(parse_directive("#includes").is_none());
+    }
+
+    #[test]
+    fn includes_within_inactive_blocks_and_comments_are_skipped() {
+        let dir = files(
+            "inactive",
+            &[
+                (
+                    "main.frag",
+                    "#if 0\n#include \"missing.glsl\"\n#endif\n\
+                     /* #include \"missing.glsl\"\n#include \"missing.glsl\" */\n\
+                     #ifdef OFF\n#include \"off.glsl\"\n#elif 1\n#include \"on.glsl\"\n#endif\n",
+                ),
+                ("off.glsl", "off\n"),
+                ("on.glsl", "on\n"),
+            ],
+        );
+        let main = dir.join("main.frag");
+        let source = fs::read_to_string(&main).unwrap();
+        let mut defines = BTreeMap::new();
+        let pp = preprocess_file_source(&main, &source, &[], &defines).unwrap();
+        // `OFF` is not known to be undefined, so either branch may be compiled.
+        assert_eq!(
+            pp.dependencies,
+            vec![dir.join("off.glsl"), dir.join("on.glsl")]
+        );
+        let mut graph = DependencyGraph::default();
+        graph.update(&main, &[]);
+        assert_eq!(graph.dependents(&dir.join("on.glsl")).count(), 1);
+
+        defines.insert("OFF".to_string(), String::new());
+        let pp = preprocess_file_source(&main, &source, &[], &defines).unwrap();
+        assert_eq!(pp.dependencies, vec![dir.join("off.glsl")]);
+        assert!(pp.source.contains("off\n"));
+        assert!(!pp.source.contains("on\n"));
+    }
+
+    #[test]
+    fn unresolved_includes_within_unknown_blocks_are_deferred() {
+        let source = "#ifdef X\n#include \"missing.glsl\"\n#endif\n";
+        let pp = preprocess_str(source, None, &[]).unwrap();
+        assert_eq!(pp.source, "#ifdef X\n#error unresolved include\n#endif\n");
+        assert!(pp.deferred_error(0).is_none());
+        match pp.deferred_error("#ifdef X\n".len()) {
+            Some(PreprocessError::NotFound { line: 2, .. }) => (),
+            other => panic!("expected a missing include, got {:?}", other),
+        }
+
+        let mut defines = BTreeMap::new();
+        defines.insert("X".to_string(), String::new());
+        let pp = preprocess_str_with_defines(source, None, &[], &defines);
+        assert!(matches!(pp, Err(PreprocessError::NotFound { line: 2, .. })));
+    }
+
+    #[test]
+    fn cycles_within_include_guards_are_skipped() {
+        let dir = files(
+            "guarded-cycle",
+            &[
+                ("main.frag", "#include \"a.glsl\"\n"),
+                (
+                    "a.glsl",
+                    "#ifndef A\n#define A\n#include \"b.glsl\"\na\n#endif\n",
+                ),
+                (
+                    "b.glsl",
+                    "#ifndef B\n#define B\n#include \"a.glsl\"\nb\n#endif\n",
+                ),
+            ],
+        );
+        let pp = preprocess_file(&dir.join("main.frag"), &[]).unwrap();
+        assert_eq!(
+            pp.dependencies,
+            vec![dir.join("a.glsl"), dir.join("b.glsl")]
+        );
+    }
+}
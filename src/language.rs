@@ -0,0 +1,58 @@
+//! The source languages from which shaders may be compiled.
+//!
+//! GLSL is always supported. WGSL and SPIR-V input are enabled by the `wgsl-in` and `spv-in`
+//! cargo features respectively, which enable the corresponding naga frontends.
+
+use crate::GLSL_EXTENSIONS;
+use std::path::Path;
+
+/// The extensions of WGSL files that will be watched and compiled.
+#[cfg(feature = "wgsl-in")]
+pub const WGSL_EXTENSIONS: &[&str] = &["wgsl"];
+
+/// The extensions of precompiled SPIR-V files that will be watched, validated and re-emitted.
+#[cfg(feature = "spv-in")]
+pub const SPIRV_EXTENSIONS: &[&str] = &["spv"];
+
+/// The language in which a shader file is written.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub enum SourceLanguage {
+    Glsl,
+    #[cfg(feature = "wgsl-in")]
+    Wgsl,
+    #[cfg(feature = "spv-in")]
+    Spirv,
+}
+
+impl SourceLanguage {
+    /// Determine the language of the shader at the given path from its extension.
+    ///
+    /// GLSL files may have a compound extension such as `foo.frag.glsl`, while WGSL and SPIR-V
+    /// files are recognised by their final extension, e.g. `foo.wgsl` or `foo.frag.spv`.
+    ///
+    /// Returns `None` if the path does not have the extension of a supported language.
+    pub fn from_path(path: &Path) -> Option<Self> {
+        let ext = path.extension().and_then(|s| s.to_str());
+        #[cfg(feature = "wgsl-in")]
+        {
+            if ext.is_some_and(|ext| WGSL_EXTENSIONS.contains(&ext)) {
+                return Some(SourceLanguage::Wgsl);
+            }
+        }
+        #[cfg(feature = "spv-in")]
+        {
+            if ext.is_some_and(|ext| SPIRV_EXTENSIONS.contains(&ext)) {
+                return Some(SourceLanguage::Spirv);
+            }
+        }
+        let compound_ext = path
+            .file_stem()
+            .map(Path::new)
+            .and_then(Path::extension)
+            .and_then(|s| s.to_str());
+        ext.into_iter()
+            .chain(compound_ext)
+            .any(|ext| GLSL_EXTENSIONS.contains(&ext))
+            .then_some(SourceLanguage::Glsl)
+    }
+}
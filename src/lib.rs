@@ -2,24 +2,122 @@
 //!
 //! See the `watch` function.
 
+pub use backend::{Backend, DEFAULT_POLL_INTERVAL};
+pub use builder::WatchBuilder;
+pub use cache::{CacheStats, DiskCache, DEFAULT_CACHE_CAPACITY, DEFAULT_DISK_CACHE_SIZE};
+pub use debounce::{Clock, ManualClock, SystemClock};
+pub use diagnostic::{CompileReport, Diagnostic, Label, Position, Severity, SourceRange};
+pub use event::ShaderEvent;
+pub use fallback::CompileOutcome;
+pub use filter::FileFilter;
+pub use include::{preprocess_file, preprocess_str, PreprocessError, Preprocessed};
+pub use language::SourceLanguage;
+#[cfg(feature = "spv-in")]
+pub use language::SPIRV_EXTENSIONS;
+#[cfg(feature = "wgsl-in")]
+pub use language::WGSL_EXTENSIONS;
+pub use library::{ShaderEntry, ShaderLibrary};
 pub use naga::ShaderStage;
-use notify::{self, Watcher};
-use std::cell::RefCell;
-use std::collections::HashSet;
+pub use options::{
+    BoundsCheckPolicies, BoundsCheckPolicy, Capabilities, CompileOptions, ValidationFlags,
+    WriterFlags,
+};
+pub use permutation::{CompiledPermutations, PermutationKey, Permutations};
+pub use program::{check_interface, CompiledProgram, Program};
+pub use reflect::{
+    BindingType, CompiledShader, ImageDimension, InterfaceType, InterfaceVariable, Interpolation,
+    PushConstantRange, Reflection, ResourceAccess, ResourceBinding, Sampling, ScalarKind,
+    StorageFormat,
+};
+pub use stage::{
+    DefaultStageResolver, StageFromCompoundExtension, StageFromExtension, StageFromPragma,
+    StageResolver,
+};
+use std::borrow::Cow;
+use std::collections::{HashMap, HashSet};
 use std::path::{Path, PathBuf};
-use std::sync::mpsc;
+use std::sync::{mpsc, Arc, Mutex, TryLockError};
+use std::time::Duration;
+#[cfg(feature = "async")]
+pub use stream::CompileStream;
+#[cfg(feature = "glsl")]
+pub use target::GlslVersion;
+#[cfg(feature = "hlsl")]
+pub use target::ShaderModel;
+pub use target::Target;
 use thiserror::Error;
+pub use worker::CompiledPath;
+
+mod backend;
+mod builder;
+mod cache;
+mod debounce;
+mod diagnostic;
+mod event;
+mod fallback;
+mod filter;
+mod include;
+mod language;
+mod library;
+mod options;
+mod permutation;
+mod program;
+mod reflect;
+mod stage;
+#[cfg(feature = "async")]
+mod stream;
+mod target;
+mod worker;
+
+#[cfg(test)]
+#[path = "../tests/common/mod.rs"]
+mod common;
 
 /// Watches one or more paths for changes to GLSL shader files.
 ///
 /// See the `watch` or `watch_paths` constructor functions.
+///
+/// A `Watch` is `Send` and `Sync`, so it may be shared between threads, e.g. within an `Arc`.
+/// `try_next_path`, `paths_touched` and the `compile_touched` methods may be called concurrently,
+/// in which case each touched path is yielded to exactly one caller.
 pub struct Watch {
-    event_rx: mpsc::Receiver<notify::Result<notify::Event>>,
-    pending_paths: RefCell<Vec<PathBuf>>,
-    _watcher: notify::RecommendedWatcher,
-    _watched_paths: Vec<PathBuf>,
+    event_rx: Mutex<mpsc::Receiver<notify::Result<notify::Event>>>,
+    events: Mutex<EventState>,
+    compile_options: CompileOptions,
+    file_filter: FileFilter,
+    ignore_cache: filter::IgnoreCache,
+    permutations: HashMap<PathBuf, Permutations>,
+    programs: Vec<Program>,
+    #[cfg(feature = "async")]
+    wakers: Arc<stream::EventWakers>,
+    cache: Arc<cache::CompileCache>,
+    last_good: fallback::LastGood,
+    interfaces: program::InterfaceChecks,
+    workers: Option<worker::CompileWorkers>,
+    backend: Backend,
+    watcher: backend::AnyWatcher,
+    watched_paths: Vec<PathBuf>,
+    max_depth: Option<usize>,
+    follow_symlinks: bool,
+}
+
+/// The state of a `Watch` that is updated as events are received.
+///
+/// Kept behind a single lock so that paths are moved from the debouncer to the pending queue and
+/// taken from the queue atomically.
+struct EventState {
+    pending_paths: Vec<PathBuf>,
+    shader_events: Vec<ShaderEvent>,
+    debouncer: debounce::Debouncer,
+    dependencies: include::DependencyGraph,
 }
 
+// Ensure `Watch` remains shareable between threads.
+const _: fn() = || {
+    fn assert_send_sync<T: Send + Sync>() {}
+    assert_send_sync::<Watch>();
+};
+
 /// Errors that might occur while creating a `Watch` instance.
 #[derive(Debug, Error)]
 #[error("failed to setup a notify watcher: {err}")]
@@ -28,6 +126,14 @@ pub struct CreationError {
     err: notify::Error,
 }
 
+/// Errors that might occur while changing the paths watched by a `Watch`.
+#[derive(Debug, Error)]
+#[error("failed to update the paths watched by notify: {err}")]
+pub struct WatchPathError {
+    #[from]
+    err: notify::Error,
+}
+
 /// Errors that might occur while waiting for the next library instance.
 #[derive(Debug, Error)]
 pub enum NextPathError {
@@ -52,7 +158,7 @@ pub enum AwaitEventError {
     },
 }
 
-/// Errors that might occur while attempting to compile a glsl file to a spir-v file.
+/// Errors that might occur while attempting to compile a shader file to the target language.
 #[derive(Debug, Error)]
 pub enum CompileError {
     #[error("an I/O error occurred: {err}")]
@@ -60,10 +166,27 @@ pub enum CompileError {
         #[from]
         err: std::io::Error,
     },
-    #[error("an error occurred compiling glsl to spir-v: {err}")]
-    GlslToSpirv {
+    #[error("could not determine the shader stage of {path:?}")]
+    UnresolvedStage { path: PathBuf },
+    #[error("failed to expand `#include` directives: {err}")]
+    Preprocess {
         #[from]
-        err: NagaError,
+        err: PreprocessError,
+    },
+    #[error("failed to compile permutation `{key}`: {err}")]
+    Permutation {
+        key: PermutationKey,
+        #[source]
+        err: Box<CompileError>,
+    },
+    #[error("the vertex outputs and fragment inputs of a program do not match")]
+    InterfaceMismatch { diagnostics: Vec<Diagnostic> },
+    /// naga failed to parse, validate or write the shader, whatever its language or target.
+    #[error("failed to compile the shader: {err}")]
+    Naga {
+        #[source]
+        err: Box<NagaError>,
+        diagnostics: Vec<Diagnostic>,
     },
 }
 
@@ -74,6 +197,18 @@ pub enum NagaError {
         #[from]
         err: NagaFrontError,
     },
+    #[cfg(feature = "wgsl-in")]
+    #[error("failed to construct a naga module from WGSL: {err}")]
+    WgslFront {
+        #[from]
+        err: naga::front::wgsl::ParseError,
+    },
+    #[cfg(feature = "spv-in")]
+    #[error("failed to construct a naga module from SPIR-V: {err}")]
+    SpvFront {
+        #[from]
+        err: naga::front::spv::Error,
+    },
     #[error("validation failed: {err}")]
     Validation {
         #[from]
@@ -84,14 +219,92 @@ pub enum NagaError {
         #[from]
         err: naga::back::spv::Error,
     },
+    #[cfg(feature = "wgsl")]
+    #[error("wgsl generation failed: {err}")]
+    Wgsl {
+        #[from]
+        err: naga::back::wgsl::Error,
+    },
+    #[cfg(feature = "msl")]
+    #[error("msl generation failed: {err}")]
+    Msl {
+        #[from]
+        err: naga::back::msl::Error,
+    },
+    #[cfg(feature = "hlsl")]
+    #[error("hlsl generation failed: {err}")]
+    Hlsl {
+        #[from]
+        err: naga::back::hlsl::Error,
+    },
+    #[cfg(feature = "glsl")]
+    #[error("glsl generation failed: {err}")]
+    Glsl {
+        #[from]
+        err: naga::back::glsl::Error,
+    },
 }
 
 #[derive(Debug)]
 pub struct NagaFrontError(Vec<naga::front::glsl::Error>);
 
+impl NagaFrontError {
+    /// Each of the errors produced by naga's GLSL frontend.
+    pub fn errors(&self) -> &[naga::front::glsl::Error] {
+        &self.0
+    }
+}
+
+impl CompileError {
+    /// Structured diagnostics describing the error.
+    ///
+    /// Errors produced by naga carry the location of the offending source, mapped back through any
+    /// `#include` directives to the file in which it was written.
+    pub fn diagnostics(&self) -> Vec<Diagnostic> {
+        match *self {
+            CompileError::Io { ref err } => vec![Diagnostic::error(err.to_string())],
+            CompileError::UnresolvedStage { ref path } => {
+                let diag = Diagnostic::error("could not determine the shader stage")
+                    .with_path(Some(path))
+                    .with_note(
+                        "use a known extension or a `#pragma shader_stage(...)` directive"
+                            .to_string(),
+                    );
+                vec![diag]
+            }
+            CompileError::Preprocess { ref err } => {
+                let diag = Diagnostic::error(err.to_string());
+                let diag = match *err {
+                    PreprocessError::Io { ref path, .. } | PreprocessError::Cycle { ref path } => {
+                        diag.with_path(Some(path))
+                    }
+                    PreprocessError::Malformed { ref path, line }
+                    | PreprocessError::NotFound { ref path, line, .. } => {
+                        let range = SourceRange::line(path.as_deref(), line);
+                        diag.with_label(range, String::new())
+                    }
+                };
+                vec![diag]
+            }
+            CompileError::Permutation { ref key, ref err } => err
+                .diagnostics()
+                .into_iter()
+                .map(|diag| diag.with_note(format!("in permutation `{}`", key)))
+                .collect(),
+            CompileError::InterfaceMismatch { ref diagnostics }
+            | CompileError::Naga {
+                ref diagnostics, ..
+            } => diagnostics.clone(),
+        }
+    }
+}
+
 impl std::fmt::Display for NagaFrontError {
     fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
-        for e in &self.0 {
+        for (ix, e) in self.0.iter().enumerate() {
+            if ix > 0 {
+                writeln!(f)?;
+            }
             e.fmt(f)?;
         }
         Ok(())
@@ -100,7 +313,10 @@ impl std::fmt::Display for NagaFrontError {
 
 impl std::error::Error for NagaFrontError {}
 
-/// The list of extensions that are considered valid shader extensions.
+/// The list of extensions that are considered valid GLSL shader extensions.
+///
+/// See also `WGSL_EXTENSIONS` and `SPIRV_EXTENSIONS` when the `wgsl-in` and `spv-in` features are
+/// enabled.
 ///
 /// There are no real official extensions for GLSL files or even an official GLSL file format, but
 /// apparently Khronos' reference GLSL compiler/validator uses these.
@@ -112,16 +328,42 @@ pub const GLSL_EXTENSIONS: &[&str] = &["vert", "frag", "comp", "vs", "fs", "cs"]
 impl Watch {
     /// Block the current thread until some filesystem event has been received from notify.
     ///
+    /// If a debounce window has been set, this instead blocks until at least one touched shader
+    /// has settled. Note that the wait is always measured in real time, so a `ManualClock` should
+    /// be driven via `try_next_path` or `paths_touched` instead.
+    ///
     /// This is useful when running the hotloading process on a separate thread.
     pub fn await_event(&self) -> Result<(), AwaitEventError> {
-        let res = match self.event_rx.recv() {
-            Ok(res) => res,
-            _ => return Err(AwaitEventError::ChannelClosed),
-        };
-        let event = res?;
-        let paths = shaders_related_to_event(&event);
-        self.pending_paths.borrow_mut().extend(paths);
-        Ok(())
+        let event_rx = self.event_rx.lock().unwrap();
+        loop {
+            let until_settled = self.events.lock().unwrap().debouncer.time_until_settled();
+            let res = match until_settled {
+                None => match event_rx.recv() {
+                    Ok(res) => res,
+                    _ => return Err(AwaitEventError::ChannelClosed),
+                },
+                Some(timeout) => match event_rx.recv_timeout(timeout) {
+                    Ok(res) => res,
+                    Err(mpsc::RecvTimeoutError::Timeout) => {
+                        self.events.lock().unwrap().settle();
+                        return Ok(());
+                    }
+                    Err(mpsc::RecvTimeoutError::Disconnected) => {
+                        return Err(AwaitEventError::ChannelClosed)
+                    }
+                },
+            };
+            let event = res?;
+            self.ignore_cache.invalidate(&event.paths);
+            let mut events = self.events.lock().unwrap();
+            events.touch(&event, &self.compile_options.include_dirs, &|p| {
+                self.is_shader(p)
+            });
+            if events.debouncer.window().is_zero() {
+                events.settle();
+                return Ok(());
+            }
+        }
     }
 
     /// Checks for a new filesystem event.
@@ -131,25 +373,148 @@ impl Watch {
     /// If the event relates to multiple shader files, the remaining files are buffered until the
     /// next call to `next` or `try_next`.
     ///
+    /// If a debounce window has been set, touched shaders are only returned once they have gone
+    /// untouched for the full window, and each only once per settle period.
+    ///
     /// Returns an `Err` if the channel was closed or if one of the notify `Watcher`s sent us an
     /// error.
     pub fn try_next_path(&self) -> Result<Option<PathBuf>, NextPathError> {
-        let mut pending_paths = self.pending_paths.borrow_mut();
         loop {
-            if !pending_paths.is_empty() {
-                return Ok(Some(pending_paths.remove(0)));
-            }
-            match self.event_rx.try_recv() {
-                Err(mpsc::TryRecvError::Disconnected) => return Err(NextPathError::ChannelClosed),
-                Err(mpsc::TryRecvError::Empty) => (),
-                Ok(res) => {
-                    let event = res?;
-                    pending_paths.extend(shaders_related_to_event(&event));
-                    continue;
+            {
+                let mut events = self.events.lock().unwrap();
+                events.settle();
+                if !events.pending_paths.is_empty() {
+                    return Ok(Some(events.pending_paths.remove(0)));
                 }
             }
+            if !self.try_recv_event()? {
+                return Ok(None);
+            }
+        }
+    }
+
+    /// Checks for the next created, modified, removed or renamed shader file.
+    ///
+    /// Unlike `try_next_path`, this reports shaders that no longer exist, e.g. so that any pipeline
+    /// built from a removed shader may be discarded. Shader events are neither debounced nor
+    /// affected by includes, and are received independently of touched paths. Events accumulate
+    /// until they are received, though those of the same shader are coalesced, e.g. a removal
+    /// followed by a creation is received as a single `Modified` event.
+    ///
+    /// A rename is reported as `ShaderEvent::Renamed` where the platform pairs both of its paths,
+    /// and otherwise as a `Removed` followed by a `Created` event.
+    pub fn try_next_shader_event(&self) -> Result<Option<ShaderEvent>, NextPathError> {
+        // Receive all pending events first so that they may be coalesced.
+        while self.try_recv_event()? {}
+        let mut events = self.events.lock().unwrap();
+        if events.shader_events.is_empty() {
             return Ok(None);
         }
+        Ok(Some(events.shader_events.remove(0)))
+    }
+
+    /// Mark every shader file at or beneath the watched paths as touched, returning the number of
+    /// shaders found.
+    ///
+    /// This is useful for loading all shaders on startup via the same `compile_touched` (or
+    /// similar) pipeline used for hot reloading. The shaders are queued in order of their paths
+    /// and bypass the debounce window.
+    pub fn touch_all(&self) -> usize {
+        let mut shaders = vec![];
+        for path in &self.watched_paths {
+            self.shader_files(path, &mut shaders);
+        }
+        shaders.sort();
+        shaders.dedup();
+        let count = shaders.len();
+        let include_dirs = &self.compile_options.include_dirs;
+        let mut events = self.events.lock().unwrap();
+        for shader in shaders {
+            events.dependencies.update(&shader, include_dirs);
+            if !events.pending_paths.contains(&shader) {
+                events.pending_paths.push(shader);
+            }
+        }
+        count
+    }
+
+    /// The notify backend in use.
+    ///
+    /// This is `Backend::Poll` if the `Watch` fell back to polling after the native backend failed.
+    /// See `WatchBuilder::poll_fallback`.
+    pub fn backend(&self) -> Backend {
+        self.backend
+    }
+
+    /// The paths currently being watched for shader changes.
+    ///
+    /// These are the paths given at construction or via `add_path`, not including the include
+    /// directories.
+    pub fn watched_paths(&self) -> &[PathBuf] {
+        &self.watched_paths
+    }
+
+    /// Begin watching the given file or directory of files for shader changes.
+    ///
+    /// The includes of all existing shaders beneath the path are scanned so that touching a shared
+    /// header also yields them. Shaders that were already touched remain pending. Does nothing if
+    /// the path is already watched.
+    pub fn add_path<P>(&mut self, path: P) -> Result<(), WatchPathError>
+    where
+        P: AsRef<Path>,
+    {
+        let path = path.as_ref().to_path_buf();
+        if self.watched_paths.contains(&path) {
+            return Ok(());
+        }
+        let recursive = self.max_depth != Some(0);
+        backend::watch_path(&mut *self.watcher, &path, recursive)?;
+        self.watched_paths.push(path);
+        let path = self.watched_paths.last().unwrap();
+        let mut shaders = vec![];
+        self.shader_files(path, &mut shaders);
+        let include_dirs = &self.compile_options.include_dirs;
+        let events = self.events.get_mut().unwrap();
+        for shader in &shaders {
+            events.dependencies.update(shader, include_dirs);
+        }
+        Ok(())
+    }
+
+    /// Stop watching the given path, returning whether or not it was being watched.
+    ///
+    /// Shaders beneath the path that were already touched remain pending, but no further changes
+    /// beneath the path are reported unless it is also within another watched path or include
+    /// directory. A path that no longer exists may be removed. If an error is returned, the path
+    /// remains watched.
+    pub fn remove_path<P>(&mut self, path: P) -> Result<bool, WatchPathError>
+    where
+        P: AsRef<Path>,
+    {
+        let path = path.as_ref();
+        let ix = match self.watched_paths.iter().position(|p| p == path) {
+            Some(ix) => ix,
+            None => return Ok(false),
+        };
+        let mut watched_paths = self.watched_paths.clone();
+        watched_paths.remove(ix);
+        let include_dirs = &self.compile_options.include_dirs;
+        let recursive = self.max_depth != Some(0);
+        let watcher = &mut *self.watcher;
+        let unwatched =
+            backend::unwatch_within(watcher, path, &watched_paths, recursive, include_dirs);
+        if let Err(err) = unwatched {
+            // Leave the path watched, as it remains within `watched_paths`.
+            backend::watch_path(watcher, path, recursive).ok();
+            return Err(err.into());
+        }
+        self.watched_paths = watched_paths;
+        let watched_paths = &self.watched_paths;
+        let events = self.events.get_mut().unwrap();
+        events.dependencies.retain(|shader| {
+            !shader.starts_with(path) || watched_paths.iter().any(|p| shader.starts_with(p))
+        });
+        Ok(true)
     }
 
     /// Returns all unique paths that have been changed at least once since the last call to
@@ -173,17 +538,630 @@ impl Watch {
 
     /// Produce an iterator that compiles each touched shader file to SPIR-V.
     ///
+    /// Each shader is compiled with the `Watch`'s `CompileOptions`, so a different `Target` may
+    /// be selected via `set_compile_options`.
+    ///
     /// Compilation of each file only begins on the produced iterator's `next` call.
     pub fn compile_touched(
         &self,
-    ) -> Result<impl Iterator<Item = (PathBuf, Result<Vec<u8>, CompileError>)>, NextPathError> {
+    ) -> Result<impl '_ + Iterator<Item = (PathBuf, Result<Vec<u8>, CompileError>)>, NextPathError>
+    {
         let paths = self.paths_touched()?;
-        let iter = paths.into_iter().map(|path| {
-            let result = compile(&path);
+        let iter = paths.into_iter().map(move |path| {
+            let result = self.compile_cached(&path);
             (path, result)
         });
         Ok(iter)
     }
+
+    /// Produce an iterator that compiles each touched shader file, yielding each result along
+    /// with the shader's last successfully compiled output.
+    ///
+    /// This allows for continuing to render with the last good output of a shader while showing
+    /// the error of its current source. The `Watch` retains the last good output of every shader
+    /// compiled via the `compile_touched` methods, `try_recv_compiled` and `into_compile_stream`,
+    /// with the exception of `compile_touched_permutations`, as it produces an output per
+    /// permutation.
+    pub fn compile_touched_with_fallback(
+        &self,
+    ) -> Result<impl '_ + Iterator<Item = CompileOutcome>, NextPathError> {
+        let paths = self.paths_touched()?;
+        let iter = paths
+            .into_iter()
+            .map(move |path| self.compile_outcome(path));
+        Ok(iter)
+    }
+
+    /// The last successfully compiled output of the shader at the given path, if any.
+    ///
+    /// See `compile_touched_with_fallback`.
+    pub fn last_good(&self, path: &Path) -> Option<Vec<u8>> {
+        self.last_good.get(path)
+    }
+
+    /// Compile all touched shader files in parallel using rayon's global thread pool.
+    ///
+    /// This is useful when many shaders change at once, e.g. after switching branches. Each
+    /// shader is compiled with the `Watch`'s `CompileOptions`. Unlike `compile_touched`, all
+    /// shaders are compiled before returning and the results are sorted by path.
+    #[cfg(feature = "rayon")]
+    pub fn compile_touched_parallel(&self) -> Result<Vec<CompiledPath>, NextPathError> {
+        use rayon::prelude::*;
+        let mut paths: Vec<PathBuf> = self.paths_touched()?.into_iter().collect();
+        paths.sort();
+        let compiled = paths
+            .into_par_iter()
+            .map(|path| {
+                let result = self.compile_cached(&path);
+                (path, result)
+            })
+            .collect();
+        Ok(compiled)
+    }
+
+    /// Receive the next shader compiled by the background workers, if any have finished.
+    ///
+    /// Each call first queues all newly touched shaders for compilation with the `Watch`'s
+    /// `CompileOptions`. Results of compiles that were made stale by the shader being touched
+    /// again are discarded.
+    ///
+    /// If no workers have been spawned via `spawn_compile_workers`, the next touched shader is
+    /// instead compiled on the calling thread.
+    pub fn try_recv_compiled(&self) -> Result<Option<CompiledPath>, NextPathError> {
+        let workers = match self.workers {
+            Some(ref workers) => workers,
+            None => {
+                let compiled = self.try_next_path()?.map(|path| {
+                    let result = self.compile_cached(&path);
+                    (path, result)
+                });
+                return Ok(compiled);
+            }
+        };
+        while let Some(path) = self.try_next_path()? {
+            workers.touch(path);
+        }
+        workers.dispatch(&self.options(), &self.cache, &self.programs);
+        let compiled = workers.try_recv().map(|((path, result), interface)| {
+            let outcome = self.record(path, result, interface);
+            (outcome.path, outcome.result)
+        });
+        Ok(compiled)
+    }
+
+    /// Spawn a pool of `workers` background threads for compiling touched shaders.
+    ///
+    /// Finished results are received via `try_recv_compiled`. At most `queue_capacity` shaders
+    /// are queued for the workers at once - further touched shaders wait until there is space. If
+    /// a shader is touched again before its previous compile has been received, the previous
+    /// compile is skipped if it has not yet started, or its result is discarded otherwise.
+    ///
+    /// Replaces any previously spawned pool, as though by `stop_compile_workers`.
+    pub fn spawn_compile_workers(&mut self, workers: usize, queue_capacity: usize) {
+        self.stop_compile_workers();
+        self.workers = Some(worker::CompileWorkers::spawn(workers, queue_capacity));
+    }
+
+    /// Stop the background workers spawned via `spawn_compile_workers`, if any.
+    ///
+    /// Shaders whose compiles have not been received, whether queued, in progress or finished,
+    /// are touched again so that they are produced by the next `compile_touched` or
+    /// `try_recv_compiled` call. Workers finish their current compile before exiting, but its
+    /// result is discarded.
+    pub fn stop_compile_workers(&mut self) {
+        let workers = match self.workers.take() {
+            Some(workers) => workers,
+            None => return,
+        };
+        let mut unreceived = workers.into_unreceived();
+        let mut events = self.events.lock().unwrap();
+        unreceived.retain(|path| !events.pending_paths.contains(path));
+        // These were touched before any paths that are still pending.
+        events.pending_paths.splice(0..0, unreceived);
+    }
+
+    /// Produce an iterator that compiles each touched shader file to SPIR-V, reflecting the
+    /// resources and interface of each shader's entry point.
+    ///
+    /// Compilation of each file only begins on the produced iterator's `next` call.
+    pub fn compile_touched_reflected(
+        &self,
+    ) -> Result<
+        impl '_ + Iterator<Item = (PathBuf, Result<CompiledShader, CompileError>)>,
+        NextPathError,
+    > {
+        let paths = self.paths_touched()?;
+        let iter = paths.into_iter().map(move |path| {
+            let options = self.options();
+            let result = self.cache.compile(&path, &options);
+            if let Ok(ref shader) = result {
+                self.last_good.insert(&path, &shader.bytes);
+                let interface = self.check_interfaces(&path, &options);
+                self.interfaces.record(&path, interface);
+            }
+            (path, result)
+        });
+        Ok(iter)
+    }
+
+    /// Produce an iterator that compiles every permutation of each touched shader file to SPIR-V.
+    ///
+    /// Shaders without any permutations registered via `set_permutations` yield a single
+    /// permutation with an empty key.
+    ///
+    /// Compilation of each file only begins on the produced iterator's `next` call.
+    pub fn compile_touched_permutations(
+        &self,
+    ) -> Result<
+        impl '_ + Iterator<Item = (PathBuf, Result<CompiledPermutations, CompileError>)>,
+        NextPathError,
+    > {
+        let paths = self.paths_touched()?;
+        let iter = paths.into_iter().map(move |path| {
+            let default = Permutations::new();
+            let permutations = self.permutations.get(&path).unwrap_or(&default);
+            let result = compile_permutations(&path, &self.options(), permutations);
+            (path, result)
+        });
+        Ok(iter)
+    }
+
+    /// Produce an iterator that compiles every registered `Program` with at least one touched
+    /// stage.
+    ///
+    /// All stages of each program are compiled and their interfaces checked via
+    /// `compile_program`. Touched shaders that are not a stage of any registered program are
+    /// ignored. Interfaces are also checked by `compile_touched` and the other compile methods
+    /// (see `add_program`), so this is only needed to receive the stages of each program together.
+    ///
+    /// Compilation of each program only begins on the produced iterator's `next` call.
+    pub fn compile_touched_programs(
+        &self,
+    ) -> Result<
+        impl '_ + Iterator<Item = (Program, Result<CompiledProgram, CompileError>)>,
+        NextPathError,
+    > {
+        let paths = self.paths_touched()?;
+        let programs: Vec<Program> = self
+            .programs
+            .iter()
+            .filter(|program| paths.iter().any(|path| program.contains(path)))
+            .cloned()
+            .collect();
+        let iter = programs.into_iter().map(move |program| {
+            let result = compile_program(&program, &self.options());
+            if let Ok(ref compiled) = result {
+                self.last_good
+                    .insert(&program.vertex, &compiled.vertex.bytes);
+                self.last_good
+                    .insert(&program.fragment, &compiled.fragment.bytes);
+            }
+            (program, result)
+        });
+        Ok(iter)
+    }
+
+    /// Register a program whose stages should be compiled and checked together.
+    ///
+    /// Whenever a stage of the program compiles as a touched shader, e.g. via `compile_touched`,
+    /// `try_recv_compiled` or `compile_touched_reflected`, the interfaces of the program's stages
+    /// are checked. The stage's output is yielded regardless, while any mismatches are available
+    /// via `interface_diagnostics` and `CompileOutcome::interface`. Checks are performed by the
+    /// background workers if spawned, and never affect the cache's counters. Permutations are not
+    /// checked, as their interfaces may differ. See also `compile_touched_programs`.
+    pub fn add_program(&mut self, program: Program) {
+        if !self.programs.contains(&program) {
+            self.programs.push(program);
+        }
+    }
+
+    /// Unregister a previously added program, returning whether or not it was registered.
+    ///
+    /// The interface mismatches recorded for the program's stages are forgotten.
+    pub fn remove_program(&mut self, program: &Program) -> bool {
+        let len = self.programs.len();
+        self.programs.retain(|p| p != program);
+        self.interfaces.forget(program);
+        self.programs.len() != len
+    }
+
+    /// Mismatches between the interface of the shader at the given path and those of the other
+    /// stages of its registered programs, as found when the shader last compiled.
+    ///
+    /// Empty if the shader's interfaces match or it is not a stage of any registered program. See
+    /// `add_program`.
+    pub fn interface_diagnostics(&self, path: &Path) -> Vec<Diagnostic> {
+        self.interfaces.get(path)
+    }
+
+    /// All registered programs.
+    pub fn programs(&self) -> &[Program] {
+        &self.programs
+    }
+
+    /// Register the set of permutations to compile for the shader at the given path.
+    ///
+    /// See `compile_touched_permutations`.
+    pub fn set_permutations<P>(&mut self, path: P, permutations: Permutations)
+    where
+        P: AsRef<Path>,
+    {
+        self.permutations
+            .insert(path.as_ref().to_path_buf(), permutations);
+    }
+
+    /// Remove the set of permutations registered for the shader at the given path.
+    pub fn remove_permutations<P>(&mut self, path: P) -> Option<Permutations>
+    where
+        P: AsRef<Path>,
+    {
+        self.permutations.remove(path.as_ref())
+    }
+
+    /// The window within which repeated events for the same shader are coalesced.
+    pub fn debounce_window(&self) -> Duration {
+        self.events.lock().unwrap().debouncer.window()
+    }
+
+    /// Set the window within which repeated events for the same shader are coalesced.
+    ///
+    /// A touched shader is only yielded once it has gone untouched for the full window, avoiding
+    /// redundant compilation when an editor produces a burst of events on save. Defaults to zero,
+    /// in which case shaders are yielded as soon as their events are received.
+    pub fn set_debounce_window(&mut self, window: Duration) {
+        self.events.get_mut().unwrap().debouncer.set_window(window);
+    }
+
+    /// Set the clock used to measure the debounce window.
+    ///
+    /// Defaults to the `SystemClock`. A `ManualClock` can be used to test debouncing
+    /// deterministically.
+    pub fn set_clock<C>(&mut self, clock: C)
+    where
+        C: 'static + Clock,
+    {
+        let events = self.events.get_mut().unwrap();
+        events.debouncer.set_clock(Arc::new(clock));
+    }
+
+    /// Set the strategy used to determine the stage of each touched shader.
+    ///
+    /// Defaults to the `DefaultStageResolver`. The cache and last good outputs are cleared.
+    pub fn set_stage_resolver<R>(&mut self, resolver: R)
+    where
+        R: 'static + StageResolver,
+    {
+        self.compile_options.stage_resolver = Arc::new(resolver);
+        self.clear_compiled();
+    }
+
+    /// The filter determining which files beneath the watched paths are treated as shaders.
+    pub fn file_filter(&self) -> &FileFilter {
+        &self.file_filter
+    }
+
+    /// Specify which files beneath the watched paths are treated as shaders.
+    ///
+    /// The includes of all shaders are re-scanned, and the cache and last good outputs are cleared.
+    /// Shaders that were already touched remain pending.
+    pub fn set_file_filter(&mut self, filter: FileFilter) {
+        self.file_filter = filter;
+        self.rescan_dependencies();
+        self.clear_compiled();
+    }
+
+    /// The directories searched when resolving `#include` directives.
+    pub fn include_dirs(&self) -> &[PathBuf] {
+        &self.compile_options.include_dirs
+    }
+
+    /// The options used to compile each touched shader.
+    pub fn compile_options(&self) -> &CompileOptions {
+        &self.compile_options
+    }
+
+    /// Set the options used to compile each touched shader.
+    ///
+    /// Any newly specified include directories are watched and those no longer specified are
+    /// unwatched. Returns an `Err` if notify failed to watch one of the new include directories,
+    /// in which case the `Watch` is left unchanged.
+    ///
+    /// The cache and the last good output of each shader are cleared, as they may be of another
+    /// `Target`.
+    pub fn set_compile_options(&mut self, options: CompileOptions) -> Result<(), CreationError> {
+        let old_dirs = &self.compile_options.include_dirs;
+        let new_dirs = &options.include_dirs;
+        let watched_paths = &self.watched_paths;
+        let recursive = self.max_depth != Some(0);
+        let watcher = &mut *self.watcher;
+
+        // Watch the new directories first so that a failure may be undone.
+        let added: Vec<_> = new_dirs.iter().filter(|d| !old_dirs.contains(d)).collect();
+        for (ix, dir) in added.iter().enumerate() {
+            if let Err(err) = watcher.watch(dir, notify::RecursiveMode::Recursive) {
+                for dir in &added[..ix] {
+                    backend::unwatch_within(watcher, dir, watched_paths, recursive, old_dirs).ok();
+                }
+                return Err(err.into());
+            }
+        }
+
+        // Failing to unwatch a directory only results in spurious events, so errors are ignored.
+        for dir in old_dirs.iter().filter(|d| !new_dirs.contains(d)) {
+            backend::unwatch_within(watcher, dir, watched_paths, recursive, new_dirs).ok();
+        }
+        self.compile_options = options;
+        let include_dirs = &self.compile_options.include_dirs;
+        let events = self.events.get_mut().unwrap();
+        events.dependencies.rescan(include_dirs);
+        self.clear_compiled();
+        Ok(())
+    }
+
+    /// The hit and miss counters of the `Watch`'s compile cache.
+    ///
+    /// Each touched shader is hashed along with the `Watch`'s `CompileOptions` before compiling.
+    /// If a shader with identical content was previously compiled successfully, e.g. because an
+    /// editor touched the file without changing it, the previous result is returned without
+    /// invoking naga. For GLSL, the content includes all expanded `#include`s.
+    pub fn cache_stats(&self) -> CacheStats {
+        self.cache.stats()
+    }
+
+    /// Set the maximum number of compiled shaders retained by the cache, replacing the existing
+    /// cache and resetting its counters.
+    ///
+    /// Defaults to `DEFAULT_CACHE_CAPACITY`. A capacity of zero disables caching.
+    pub fn set_cache_capacity(&mut self, capacity: usize) {
+        self.cache = Arc::new(cache::CompileCache::new(capacity));
+    }
+
+    /// Remove all compiled shaders from the cache.
+    pub fn clear_cache(&self) {
+        self.cache.clear();
+    }
+
+    /// Whether or not the given path is a shader according to the `Watch`'s `FileFilter`.
+    ///
+    /// The file need not exist, e.g. so that removed shaders can be recognised.
+    fn is_shader(&self, path: &Path) -> bool {
+        let ignores = &self.ignore_cache;
+        self.file_filter
+            .is_shader(path, &self.watched_paths, ignores)
+            && self.is_within_scope(path)
+    }
+
+    /// Whether or not the path is within the maximum depth of the watched path containing it and,
+    /// unless following symlinks, is not beneath a symlink.
+    fn is_within_scope(&self, path: &Path) -> bool {
+        let root = match filter::root_of(path, &self.watched_paths) {
+            Some(root) => root,
+            None => return true,
+        };
+        let rel = path.strip_prefix(root).unwrap_or(path);
+        let dirs = rel.components().count().saturating_sub(1);
+        if self.max_depth.is_some_and(|max| dirs > max) {
+            return false;
+        }
+        if !self.follow_symlinks {
+            let mut ancestor = root.to_path_buf();
+            for name in rel.components() {
+                ancestor.push(name);
+                let metadata = std::fs::symlink_metadata(&ancestor);
+                if metadata.is_ok_and(|m| m.file_type().is_symlink()) {
+                    return false;
+                }
+            }
+        }
+        true
+    }
+
+    /// Collect the paths of all shader files at or beneath the given watched path.
+    ///
+    /// Directories excluded by the filter or beyond the maximum depth are skipped. Ignore files
+    /// are re-read in case a change to one was not reported, e.g. beyond the maximum depth.
+    fn shader_files(&self, root: &Path, files: &mut Vec<PathBuf>) {
+        self.ignore_cache.clear();
+        self.collect_shader_files(root, 0, files);
+    }
+
+    fn collect_shader_files(&self, path: &Path, depth: usize, files: &mut Vec<PathBuf>) {
+        // The watched path itself is always followed.
+        let metadata = if depth == 0 || self.follow_symlinks {
+            std::fs::metadata(path)
+        } else {
+            std::fs::symlink_metadata(path)
+        };
+        let metadata = match metadata {
+            Ok(metadata) => metadata,
+            Err(_) => return,
+        };
+        if metadata.is_dir() {
+            let beyond_depth = self.max_depth.is_some_and(|max| depth > max);
+            let filter = &self.file_filter;
+            let ignores = &self.ignore_cache;
+            if beyond_depth || filter.is_excluded(path, true, &self.watched_paths, ignores) {
+                return;
+            }
+            if let Ok(entries) = std::fs::read_dir(path) {
+                for entry in entries.flatten() {
+                    self.collect_shader_files(&entry.path(), depth + 1, files);
+                }
+            }
+        } else if metadata.is_file() && self.is_shader(path) {
+            files.push(path.to_path_buf());
+        }
+    }
+
+    /// Re-scan the includes of every shader beneath the watched paths.
+    fn rescan_dependencies(&mut self) {
+        let mut shaders = vec![];
+        for path in &self.watched_paths {
+            self.shader_files(path, &mut shaders);
+        }
+        let include_dirs = &self.compile_options.include_dirs;
+        let events = self.events.get_mut().unwrap();
+        events.dependencies = include::DependencyGraph::default();
+        for shader in &shaders {
+            events.dependencies.update(shader, include_dirs);
+        }
+    }
+
+    /// The options with which touched shaders are compiled.
+    ///
+    /// These are the `Watch`'s `CompileOptions`, though the stages of the `FileFilter`'s extensions
+    /// take precedence over the stage resolver.
+    fn options(&self) -> Cow<'_, CompileOptions> {
+        if !self.file_filter.has_stages() {
+            return Cow::Borrowed(&self.compile_options);
+        }
+        let fallback = self.compile_options.stage_resolver.clone();
+        let resolver = filter::ExtensionStages::new(self.file_filter.clone(), fallback);
+        let options = CompileOptions {
+            stage_resolver: Arc::new(resolver),
+            ..self.compile_options.clone()
+        };
+        Cow::Owned(options)
+    }
+
+    /// Receive a single pending notify event, if any, returning whether or not one was received.
+    fn try_recv_event(&self) -> Result<bool, NextPathError> {
+        // If another thread is blocked within `await_event`, it is already receiving events.
+        let event_rx = match self.event_rx.try_lock() {
+            Ok(event_rx) => event_rx,
+            Err(TryLockError::WouldBlock) => return Ok(false),
+            Err(TryLockError::Poisoned(err)) => err.into_inner(),
+        };
+        match event_rx.try_recv() {
+            Err(mpsc::TryRecvError::Disconnected) => Err(NextPathError::ChannelClosed),
+            Err(mpsc::TryRecvError::Empty) => Ok(false),
+            Ok(res) => {
+                let event = res?;
+                self.ignore_cache.invalidate(&event.paths);
+                let mut events = self.events.lock().unwrap();
+                events.touch(&event, &self.compile_options.include_dirs, &|p| {
+                    self.is_shader(p)
+                });
+                Ok(true)
+            }
+        }
+    }
+
+    /// Compile the shader at the given path via the cache. See `compile_outcome`.
+    fn compile_cached(&self, path: &Path) -> Result<Vec<u8>, CompileError> {
+        self.compile_outcome(path.to_path_buf()).result
+    }
+
+    /// Compile the shader at the given path via the cache, recording its last good output and
+    /// the interface mismatches of the registered programs of which it is a stage.
+    fn compile_outcome(&self, path: PathBuf) -> CompileOutcome {
+        let options = self.options();
+        let result = self.cache.compile_bytes(&path, &options);
+        let interface = match result {
+            Ok(_) => self.check_interfaces(&path, &options),
+            Err(_) => vec![],
+        };
+        self.record(path, result, interface)
+    }
+
+    /// Check the interfaces of the registered programs of which the shader at the given path is
+    /// a stage.
+    fn check_interfaces(&self, path: &Path, options: &CompileOptions) -> Vec<Diagnostic> {
+        program::check_stage(&self.programs, path, options, &self.cache)
+    }
+
+    /// Record the result of compiling the shader at the given path along with the interface
+    /// mismatches of its programs.
+    ///
+    /// The mismatches of a shader that failed to compile are left as they were, as they still
+    /// apply to its last good output.
+    fn record(
+        &self,
+        path: PathBuf,
+        result: Result<Vec<u8>, CompileError>,
+        interface: Vec<Diagnostic>,
+    ) -> CompileOutcome {
+        let last_good = self.last_good.record(&path, &result);
+        if result.is_ok() {
+            self.interfaces.record(&path, interface.clone());
+        }
+        CompileOutcome {
+            path,
+            result,
+            last_good,
+            interface,
+        }
+    }
+
+    /// Discard all previously compiled output, as it may no longer correspond to the options with
+    /// which shaders are compiled.
+    fn clear_compiled(&mut self) {
+        self.cache.clear();
+        self.last_good.clear();
+        self.interfaces.clear();
+    }
+}
+
+impl EventState {
+    /// Move all touched paths that have settled into the pending queue.
+    fn settle(&mut self) {
+        for path in self.debouncer.take_settled() {
+            if !self.pending_paths.contains(&path) {
+                self.pending_paths.push(path);
+            }
+        }
+    }
+
+    /// Touch all shaders related to the given event within the debouncer.
+    ///
+    /// Also queues the shader events described by the event.
+    fn touch(
+        &mut self,
+        event: &notify::Event,
+        include_dirs: &[PathBuf],
+        is_shader: &dyn Fn(&Path) -> bool,
+    ) {
+        for shader_event in event::shader_events(event, is_shader) {
+            if let Some(removed) = shader_event.removed_path() {
+                self.dependencies.update(removed, include_dirs);
+            }
+            event::queue_shader_event(&mut self.shader_events, shader_event);
+        }
+        let paths = self.shaders_related_to_event(event, include_dirs, is_shader);
+        self.debouncer.touch(paths);
+    }
+
+    /// Checks whether or not the event relates to some shader files, and if so, returns the paths
+    /// to those shader files.
+    ///
+    /// If the event touches a file included by one or more shaders, the paths of all shaders that
+    /// transitively include it are returned. The include dependencies of all returned shaders are
+    /// re-scanned.
+    fn shaders_related_to_event(
+        &mut self,
+        event: &notify::Event,
+        include_dirs: &[PathBuf],
+        is_shader: &dyn Fn(&Path) -> bool,
+    ) -> Vec<PathBuf> {
+        let dependencies = &mut self.dependencies;
+        let mut paths: Vec<PathBuf> = vec![];
+        for path in &event.paths {
+            let dependents = dependencies.dependents(path).cloned();
+            let touched = Some(path.to_path_buf())
+                .filter(|p| p.is_file() && is_shader(p))
+                .into_iter()
+                .chain(dependents);
+            for shader in touched {
+                if !paths.contains(&shader) {
+                    paths.push(shader);
+                }
+            }
+        }
+        for path in &paths {
+            dependencies.update(path, include_dirs);
+        }
+        paths
+    }
 }
 
 /// Watch the give file or directory of files.
@@ -195,113 +1173,383 @@ where
 }
 
 /// Watch each of the specified paths for events.
+///
+/// See `WatchBuilder` for further configuration, e.g. of the notify backend.
 pub fn watch_paths<I>(paths: I) -> Result<Watch, CreationError>
 where
     I: IntoIterator,
     I::Item: AsRef<Path>,
 {
-    // Channel for sending events back to the main thread.
-    let (tx, event_rx) = mpsc::channel();
+    watch_paths_with_options(paths, CompileOptions::default())
+}
 
-    // Create a watcher for each path.
-    let mut watched_paths = vec![];
-    let mut watcher = notify::recommended_watcher(move |res| {
-        tx.send(res).ok();
-    })?;
-    for path in paths {
-        let path = path.as_ref().to_path_buf();
-        if path.is_dir() {
-            watcher.watch(&path, notify::RecursiveMode::Recursive)?;
-        } else {
-            watcher.watch(&path, notify::RecursiveMode::NonRecursive)?;
-        }
-        watched_paths.push(path);
-    }
+/// Watch each of the specified paths for events, resolving `#include` directives against the given
+/// include directories.
+///
+/// The include directories are watched too, so that touching a shared header yields every shader
+/// that transitively includes it.
+pub fn watch_paths_with_includes<I, J>(paths: I, include_dirs: J) -> Result<Watch, CreationError>
+where
+    I: IntoIterator,
+    I::Item: AsRef<Path>,
+    J: IntoIterator,
+    J::Item: AsRef<Path>,
+{
+    watch_paths_with_options(paths, CompileOptions::default().include_dirs(include_dirs))
+}
 
-    let pending_paths = RefCell::new(vec![]);
-    Ok(Watch {
-        event_rx,
-        pending_paths,
-        _watcher: watcher,
-        _watched_paths: watched_paths,
-    })
+/// Watch each of the specified paths for events, compiling touched shaders with the given options.
+///
+/// The options' include directories are watched too, so that touching a shared header yields every
+/// shader that transitively includes it.
+pub fn watch_paths_with_options<I>(
+    paths: I,
+    options: CompileOptions,
+) -> Result<Watch, CreationError>
+where
+    I: IntoIterator,
+    I::Item: AsRef<Path>,
+{
+    WatchBuilder::new()
+        .paths(paths)
+        .compile_options(options)
+        .build()
 }
 
-/// Checks whether or not the event relates to some shader file, and if so, returns the path to
-/// that shader file.
-fn shaders_related_to_event<'a>(event: &'a notify::Event) -> impl 'a + Iterator<Item = PathBuf> {
-    event.paths.iter().filter_map(|p| {
-        if path_is_shader_file(p) {
-            Some(p.to_path_buf())
-        } else {
-            None
-        }
-    })
+/// Compile the shader file at the given path to SPIR-V.
+///
+/// The source language is determined by the path's extension. See `SourceLanguage`.
+///
+/// The shader stage is determined by the `DefaultStageResolver`. `#include` directives are
+/// resolved relative to the including file.
+///
+/// Returns a `Vec<u8>` containing raw SPIR-V bytes.
+pub fn compile(glsl_path: &Path) -> Result<Vec<u8>, CompileError> {
+    compile_with_options(glsl_path, &CompileOptions::default())
 }
 
-/// Whether or not the given path is a shader file.
+/// Compile the GLSL file at the given path to SPIR-V, resolving `#include` directives against the
+/// given include directories.
 ///
-/// This is used when watching directories to distinguish between files that are shaders and those
-/// that are not.
-fn path_is_shader_file(path: &Path) -> bool {
-    if path.is_file() {
-        let path_ext = match path.extension().and_then(|s| s.to_str()) {
-            None => return false,
-            Some(ext) => ext,
-        };
-        for ext in GLSL_EXTENSIONS {
-            if &path_ext == ext {
-                return true;
-            }
-        }
-    }
-    false
+/// Quoted includes are first resolved relative to the including file. The shader stage is
+/// determined by the `DefaultStageResolver`.
+///
+/// Returns a `Vec<u8>` containing raw SPIR-V bytes.
+pub fn compile_with_includes(
+    glsl_path: &Path,
+    include_dirs: &[PathBuf],
+) -> Result<Vec<u8>, CompileError> {
+    let options = CompileOptions::default().include_dirs(include_dirs);
+    compile_with_options(glsl_path, &options)
 }
 
-/// Compile the GLSL file at the given path to SPIR-V.
+/// Compile the GLSL file at the given path to SPIR-V, determining its stage with the given
+/// `StageResolver`.
 ///
-/// The shader type is inferred from the path extension.
+/// `#include` directives are resolved as described for `compile_with_includes`.
 ///
 /// Returns a `Vec<u8>` containing raw SPIR-V bytes.
-pub fn compile(glsl_path: &Path) -> Result<Vec<u8>, CompileError> {
-    // Infer the shader type.
-    let shader_ty = glsl_path
-        .extension()
-        .and_then(|s| s.to_str())
-        .and_then(ext_to_shader_ty)
-        .expect("the given GLSL path was not a shader");
+pub fn compile_with_stage_resolver(
+    glsl_path: &Path,
+    include_dirs: &[PathBuf],
+    stage_resolver: &dyn StageResolver,
+) -> Result<Vec<u8>, CompileError> {
+    let options = CompileOptions::default().include_dirs(include_dirs);
+    compile_file(glsl_path, &options, stage_resolver).map(|shader| shader.bytes)
+}
+
+/// Compile the shader file at the given path to the options' `Target`, SPIR-V by default.
+///
+/// The source language is determined by the path's extension. See `SourceLanguage`.
+///
+/// If the options specify a `DiskCache`, a previously cached artifact is returned if the shader
+/// is unchanged.
+///
+/// Returns a `Vec<u8>` containing raw SPIR-V bytes, or UTF-8 source for text targets.
+pub fn compile_with_options(
+    glsl_path: &Path,
+    options: &CompileOptions,
+) -> Result<Vec<u8>, CompileError> {
+    match options.disk_cache {
+        Some(ref disk) => disk.compile(glsl_path, options),
+        None => compile_reflected(glsl_path, options).map(|shader| shader.bytes),
+    }
+}
+
+/// Compile the GLSL file at the given path to SPIR-V with the given options, reflecting the
+/// resources and interface of its entry point.
+pub fn compile_reflected(
+    glsl_path: &Path,
+    options: &CompileOptions,
+) -> Result<CompiledShader, CompileError> {
+    compile_file(glsl_path, options, &*options.stage_resolver)
+}
+
+/// Compile each stage of the given program and check that the vertex outputs match the fragment
+/// inputs.
+///
+/// Returns `CompileError::InterfaceMismatch` with a diagnostic for each mismatch if the stages
+/// compiled but their interfaces do not match.
+pub fn compile_program(
+    program: &Program,
+    options: &CompileOptions,
+) -> Result<CompiledProgram, CompileError> {
+    let resolver = &*options.stage_resolver;
+    let vertex = compile_file(&program.vertex, options, resolver)?;
+    let fragment = compile_file(&program.fragment, options, resolver)?;
+    let diagnostics = check_interface(
+        &vertex.reflection,
+        &program.vertex,
+        &fragment.reflection,
+        &program.fragment,
+    );
+    if !diagnostics.is_empty() {
+        return Err(CompileError::InterfaceMismatch { diagnostics });
+    }
+    Ok(CompiledProgram { vertex, fragment })
+}
 
-    // Compile to spirv.
-    let glsl_string = std::fs::read_to_string(glsl_path)?;
-    compile_str(&glsl_string, shader_ty)
+/// Compile every permutation of the GLSL file at the given path to SPIR-V.
+///
+/// Each permutation's macro definitions are applied on top of those within `options`. The file is
+/// only read and preprocessed once.
+///
+/// Returns a map from each permutation's key to its raw SPIR-V bytes, or the error produced by the
+/// first permutation that failed to compile.
+pub fn compile_permutations(
+    glsl_path: &Path,
+    options: &CompileOptions,
+    permutations: &Permutations,
+) -> Result<CompiledPermutations, CompileError> {
+    let (preprocessed, shader_ty) =
+        preprocess_and_resolve(glsl_path, options, &*options.stage_resolver)?;
+    permutations
+        .keys()
+        .into_iter()
+        .map(|key| {
+            let defines = key.defines().iter();
+            let options = options.clone().defines(defines);
+            match compile_expanded(&preprocessed, shader_ty, &options) {
+                Ok(shader) => Ok((key, shader.bytes)),
+                Err(err) => {
+                    let err = Box::new(err);
+                    Err(CompileError::Permutation { key, err })
+                }
+            }
+        })
+        .collect()
 }
 
 /// Compile the GLSL string to SPIR-V.
 ///
 /// Returns a `Vec<u8>` containing raw SPIR-V bytes.
 pub fn compile_str(glsl_str: &str, stage: ShaderStage) -> Result<Vec<u8>, CompileError> {
+    compile_str_with_options(glsl_str, stage, &CompileOptions::default())
+}
+
+/// Compile the GLSL string to the options' `Target`, SPIR-V by default.
+///
+/// `#include` directives are resolved against the options' include directories.
+///
+/// Returns a `Vec<u8>` containing raw SPIR-V bytes, or UTF-8 source for text targets.
+pub fn compile_str_with_options(
+    glsl_str: &str,
+    stage: ShaderStage,
+    options: &CompileOptions,
+) -> Result<Vec<u8>, CompileError> {
+    compile_str_reflected(glsl_str, stage, options).map(|shader| shader.bytes)
+}
+
+/// Compile the GLSL string to SPIR-V with the given options, reflecting the resources and
+/// interface of its entry point.
+pub fn compile_str_reflected(
+    glsl_str: &str,
+    stage: ShaderStage,
+    options: &CompileOptions,
+) -> Result<CompiledShader, CompileError> {
+    let include_dirs = &options.include_dirs;
+    let preprocessed =
+        include::preprocess_str_with_defines(glsl_str, None, include_dirs, &options.defines)?;
+    compile_expanded(&preprocessed, stage, options)
+}
+
+/// Compile the shader file at the given path, dispatching on its `SourceLanguage`.
+///
+/// For GLSL this expands the includes of the file, resolves its stage and compiles it. Files with
+/// an unrecognised extension are treated as GLSL.
+fn compile_file(
+    path: &Path,
+    options: &CompileOptions,
+    stage_resolver: &dyn StageResolver,
+) -> Result<CompiledShader, CompileError> {
+    let language = SourceLanguage::from_path(path).unwrap_or(SourceLanguage::Glsl);
+    match language {
+        SourceLanguage::Glsl => {
+            let (preprocessed, shader_ty) = preprocess_and_resolve(path, options, stage_resolver)?;
+            compile_expanded(&preprocessed, shader_ty, options)
+        }
+        #[cfg(feature = "wgsl-in")]
+        SourceLanguage::Wgsl => {
+            let source = std::fs::read_to_string(path)?;
+            compile_wgsl(path, source, options, stage_resolver)
+        }
+        #[cfg(feature = "spv-in")]
+        SourceLanguage::Spirv => {
+            let bytes = std::fs::read(path)?;
+            compile_spv(path, &bytes, options, stage_resolver)
+        }
+    }
+}
+
+/// Expand the includes of the file at the given path and resolve its stage.
+fn preprocess_and_resolve(
+    glsl_path: &Path,
+    options: &CompileOptions,
+    stage_resolver: &dyn StageResolver,
+) -> Result<(Preprocessed, ShaderStage), CompileError> {
+    // Read the file separately so that failing to do so is reported as an I/O error.
+    let source = std::fs::read_to_string(glsl_path)?;
+
+    // Expand includes.
+    let include_dirs = &options.include_dirs;
+    let preprocessed =
+        include::preprocess_file_source(glsl_path, &source, include_dirs, &options.defines)?;
+
+    // Determine the shader stage.
+    let shader_ty = stage_resolver
+        .resolve_stage(glsl_path, &preprocessed.source)
+        .ok_or_else(|| CompileError::UnresolvedStage {
+            path: glsl_path.to_path_buf(),
+        })?;
+
+    Ok((preprocessed, shader_ty))
+}
+
+/// Compile the WGSL source read from the file at the given path.
+///
+/// The stage is determined by the given resolver, falling back to that of the module's first
+/// entry point.
+#[cfg(feature = "wgsl-in")]
+fn compile_wgsl(
+    wgsl_path: &Path,
+    source: String,
+    options: &CompileOptions,
+    stage_resolver: &dyn StageResolver,
+) -> Result<CompiledShader, CompileError> {
+    let source = Preprocessed::verbatim(source, Some(wgsl_path));
+    let module = naga::front::wgsl::parse_str(&source.source).map_err(|err| {
+        let diagnostics = vec![Diagnostic::from_wgsl(&err, &source)];
+        let err = Box::new(NagaError::WgslFront { err });
+        CompileError::Naga { err, diagnostics }
+    })?;
+    let stage = resolve_module_stage(wgsl_path, &source.source, &module, stage_resolver)?;
+    compile_module(&module, &source, stage, options)
+}
+
+/// Validate and re-emit the precompiled SPIR-V read from the file at the given path.
+///
+/// The stage is determined by the given resolver, falling back to that of the module's first
+/// entry point.
+#[cfg(feature = "spv-in")]
+fn compile_spv(
+    spv_path: &Path,
+    bytes: &[u8],
+    options: &CompileOptions,
+    stage_resolver: &dyn StageResolver,
+) -> Result<CompiledShader, CompileError> {
+    let source = Preprocessed::verbatim(String::new(), Some(spv_path));
+    let opts = naga::front::spv::Options::default();
+    let module = naga::front::spv::parse_u8_slice(bytes, &opts).map_err(|err| {
+        let diag = Diagnostic::error(err.to_string()).with_path(Some(spv_path));
+        let diagnostics = vec![diag];
+        let err = Box::new(NagaError::SpvFront { err });
+        CompileError::Naga { err, diagnostics }
+    })?;
+    let stage = resolve_module_stage(spv_path, "", &module, stage_resolver)?;
+    compile_module(&module, &source, stage, options)
+}
+
+/// Resolve the stage of a module parsed from a language other than GLSL.
+#[cfg(any(feature = "wgsl-in", feature = "spv-in"))]
+fn resolve_module_stage(
+    path: &Path,
+    source: &str,
+    module: &naga::Module,
+    stage_resolver: &dyn StageResolver,
+) -> Result<ShaderStage, CompileError> {
+    stage_resolver
+        .resolve_stage(path, source)
+        .or_else(|| module.entry_points.first().map(|ep| ep.stage))
+        .ok_or_else(|| CompileError::UnresolvedStage {
+            path: path.to_path_buf(),
+        })
+}
+
+/// Compile GLSL that has already had its `#include` directives expanded.
+///
+/// Any errors produced by naga are accompanied by diagnostics mapped back to the original source.
+fn compile_expanded(
+    preprocessed: &Preprocessed,
+    stage: ShaderStage,
+    options: &CompileOptions,
+) -> Result<CompiledShader, CompileError> {
     let mut frontend = naga::front::glsl::Frontend::default();
-    let opts = naga::front::glsl::Options::from(stage);
+    let opts = options.glsl_options(stage);
     let module = frontend
-        .parse(&opts, glsl_str)
-        .map_err(NagaFrontError)
-        .map_err(|err| NagaError::Front { err })?;
-    let flags = naga::valid::ValidationFlags::default();
-    let caps = naga::valid::Capabilities::all();
+        .parse(&opts, &preprocessed.source)
+        .map_err(|errors| {
+            // Report includes that could not be expanded within blocks naga compiled.
+            let deferred = errors
+                .iter()
+                .filter_map(|err| err.meta.to_range())
+                .find_map(|range| preprocessed.deferred_error(range.start));
+            if let Some(err) = deferred {
+                return CompileError::Preprocess { err };
+            }
+            let diagnostics = Diagnostic::from_front(&errors, preprocessed)
+                .into_iter()
+                .map(|diag| diag.with_stage(stage))
+                .collect();
+            let err = NagaError::Front {
+                err: NagaFrontError(errors),
+            };
+            let err = Box::new(err);
+            CompileError::Naga { err, diagnostics }
+        })?;
+    compile_module(&module, preprocessed, stage, options)
+}
+
+/// Validate the given module and write it to the options' target.
+///
+/// The `source` from which the module was parsed is used to map validation errors to diagnostics.
+fn compile_module(
+    module: &naga::Module,
+    source: &Preprocessed,
+    stage: ShaderStage,
+    options: &CompileOptions,
+) -> Result<CompiledShader, CompileError> {
+    let flags = options.validation_flags;
+    let caps = options.capabilities;
     let info = naga::valid::Validator::new(flags, caps)
-        .validate(&module)
-        .map_err(|err| NagaError::Validation { err })?;
-    let opts = naga::back::spv::Options::default();
-    let pl_opts = None;
-    let spv_words = naga::back::spv::write_vec(&module, &info, &opts, pl_opts)
-        .map_err(|err| NagaError::Back { err })?;
-    let spv_bytes = spv_words
-        .iter()
-        .fold(Vec::with_capacity(spv_words.len() * 4), |mut v, w| {
-            v.extend_from_slice(&w.to_le_bytes());
-            v
-        });
-    Ok(spv_bytes)
+        .validate(module)
+        .map_err(|err| {
+            let diag = Diagnostic::from_validation(&err, source).with_stage(stage);
+            let diagnostics = vec![diag];
+            let err = NagaError::Validation { err };
+            let err = Box::new(err);
+            CompileError::Naga { err, diagnostics }
+        })?;
+    let bytes = target::write(module, &info, stage, options).map_err(|err| {
+        let diag = Diagnostic::error(err.to_string())
+            .with_path(source.path())
+            .with_stage(stage);
+        let diagnostics = vec![diag];
+        let err = Box::new(err);
+        CompileError::Naga { err, diagnostics }
+    })?;
+    let entry_point = options.entry_point.as_deref();
+    let reflection = Reflection::new(module, stage, entry_point);
+    Ok(CompiledShader { bytes, reflection })
 }
 
 /// Convert the given file extension to a shader type for `glsl_to_spirv` compilation.
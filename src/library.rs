@@ -0,0 +1,188 @@
+//! A store of the latest compiled output of every watched shader.
+//!
+//! See `ShaderLibrary`.
+
+use crate::{CompileError, NextPathError, ShaderEvent, Watch};
+use std::collections::HashMap;
+use std::path::{Path, PathBuf};
+
+/// Owns the latest compiled output of every shader beneath the paths of a `Watch`.
+///
+/// Rather than consuming `compile_touched` itself, render code may call `update` once per frame
+/// and compare the `generation` of each entry against the generation with which it last built a
+/// pipeline. Generations are drawn from a single counter shared by all entries, so an entry's
+/// generation only ever increases, even if its shader is removed and later re-created.
+///
+/// Entries are keyed by path, and may also be looked up by a logical name via `set_name`.
+pub struct ShaderLibrary {
+    watch: Watch,
+    entries: HashMap<PathBuf, ShaderEntry>,
+    names: HashMap<String, PathBuf>,
+    generation: u64,
+}
+
+/// The latest compiled output of a single shader within a `ShaderLibrary`.
+#[derive(Debug)]
+pub struct ShaderEntry {
+    bytes: Option<Vec<u8>>,
+    error: Option<CompileError>,
+    generation: u64,
+}
+
+impl ShaderLibrary {
+    /// Create a library of the shaders beneath the paths of the given `Watch`.
+    ///
+    /// Every existing shader is marked as touched, so that the first call to `update` compiles all
+    /// of them.
+    pub fn new(watch: Watch) -> Self {
+        watch.touch_all();
+        ShaderLibrary {
+            watch,
+            entries: HashMap::new(),
+            names: HashMap::new(),
+            generation: 0,
+        }
+    }
+
+    /// Compile all touched shaders and forget those that were removed, returning the paths of all
+    /// entries whose output or error changed.
+    ///
+    /// An entry's generation is only advanced when its output changes. If a shader fails to
+    /// compile, its entry retains the last successfully compiled output and records the error, and
+    /// its path is returned without advancing its generation.
+    pub fn update(&mut self) -> Result<Vec<PathBuf>, NextPathError> {
+        let mut changed = vec![];
+        while let Some(event) = self.watch.try_next_shader_event()? {
+            let removed = match event {
+                ShaderEvent::Removed(ref path) => path,
+                ShaderEvent::Renamed { ref from, .. } => from,
+                ShaderEvent::Created(_) | ShaderEvent::Modified(_) => continue,
+            };
+            if self.entries.remove(removed).is_some() {
+                changed.push(removed.clone());
+            }
+        }
+        let compiled: Vec<_> = self.watch.compile_touched()?.collect();
+        for (path, result) in compiled {
+            if !path.is_file() {
+                continue;
+            }
+            let (bytes, error) = match result {
+                Ok(bytes) => (Some(bytes), None),
+                Err(err) => (None, Some(err)),
+            };
+            match self.entries.get_mut(&path) {
+                Some(entry) => {
+                    let message =
+                        |error: &Option<CompileError>| error.as_ref().map(|e| e.to_string());
+                    let error_changed = message(&entry.error) != message(&error);
+                    entry.error = error;
+                    if bytes.is_some() && bytes != entry.bytes {
+                        self.generation += 1;
+                        entry.bytes = bytes;
+                        entry.generation = self.generation;
+                    } else if !error_changed {
+                        continue;
+                    }
+                }
+                None => {
+                    self.generation += 1;
+                    let entry = ShaderEntry {
+                        bytes,
+                        error,
+                        generation: self.generation,
+                    };
+                    self.entries.insert(path.clone(), entry);
+                }
+            }
+            if !changed.contains(&path) {
+                changed.push(path);
+            }
+        }
+        changed.sort();
+        Ok(changed)
+    }
+
+    /// The entry of the shader at the given path, if it has been compiled.
+    pub fn get(&self, path: &Path) -> Option<&ShaderEntry> {
+        self.entries.get(path)
+    }
+
+    /// The entry of the shader with the given logical name, if it has been compiled.
+    pub fn get_named(&self, name: &str) -> Option<&ShaderEntry> {
+        self.names.get(name).and_then(|path| self.get(path))
+    }
+
+    /// Refer to the shader at the given path by the given logical name, e.g. `"sky"`.
+    ///
+    /// Returns the path previously given the name, if any. The shader need not exist yet.
+    pub fn set_name<S, P>(&mut self, name: S, path: P) -> Option<PathBuf>
+    where
+        S: Into<String>,
+        P: AsRef<Path>,
+    {
+        self.names.insert(name.into(), path.as_ref().to_path_buf())
+    }
+
+    /// Forget the given logical name, returning the path it referred to.
+    pub fn remove_name(&mut self, name: &str) -> Option<PathBuf> {
+        self.names.remove(name)
+    }
+
+    /// The path of the shader with the given logical name.
+    pub fn path_of(&self, name: &str) -> Option<&Path> {
+        self.names.get(name).map(|path| path.as_path())
+    }
+
+    /// The path and entry of every compiled shader.
+    pub fn entries(&self) -> impl '_ + Iterator<Item = (&Path, &ShaderEntry)> {
+        self.entries
+            .iter()
+            .map(|(path, entry)| (path.as_path(), entry))
+    }
+
+    /// The generation most recently given to any entry, or `0` if no shader has been compiled.
+    ///
+    /// This may be compared against a previous value to check whether anything has changed.
+    /// Removing an entry does not advance the generation.
+    pub fn generation(&self) -> u64 {
+        self.generation
+    }
+
+    /// The `Watch` from which shaders are received.
+    pub fn watch(&self) -> &Watch {
+        &self.watch
+    }
+
+    /// The `Watch` from which shaders are received, e.g. to add paths or change compile options.
+    ///
+    /// Existing entries are not recompiled after changing compile options unless the shaders are
+    /// touched again, e.g. via `Watch::touch_all`.
+    pub fn watch_mut(&mut self) -> &mut Watch {
+        &mut self.watch
+    }
+
+    /// Consume the library, returning its `Watch`.
+    pub fn into_watch(self) -> Watch {
+        self.watch
+    }
+}
+
+impl ShaderEntry {
+    /// The most recent successfully compiled output of the shader.
+    ///
+    /// Returns `None` if the shader has never compiled successfully.
+    pub fn bytes(&self) -> Option<&[u8]> {
+        self.bytes.as_deref()
+    }
+
+    /// The error produced by the most recent compilation of the shader, if it failed.
+    pub fn error(&self) -> Option<&CompileError> {
+        self.error.as_ref()
+    }
+
+    /// The generation at which the entry's output last changed.
+    pub fn generation(&self) -> u64 {
+        self.generation
+    }
+}
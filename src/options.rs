@@ -0,0 +1,235 @@
+//! Options for configuring how shaders are compiled.
+
+use crate::{DefaultStageResolver, DiskCache, StageResolver, Target};
+pub use naga::back::spv::WriterFlags;
+pub use naga::proc::{BoundsCheckPolicies, BoundsCheckPolicy};
+pub use naga::valid::{Capabilities, ValidationFlags};
+use std::collections::BTreeMap;
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+
+/// Describes how GLSL should be preprocessed, validated and written to the target language.
+///
+/// Construct with `CompileOptions::default()` and configure using the builder methods. A `Watch`
+/// carries a set of options that is used for every hot recompile.
+#[derive(Clone)]
+pub struct CompileOptions {
+    pub(crate) include_dirs: Vec<PathBuf>,
+    pub(crate) stage_resolver: Arc<dyn StageResolver>,
+    pub(crate) defines: BTreeMap<String, String>,
+    pub(crate) validation_flags: ValidationFlags,
+    pub(crate) capabilities: Capabilities,
+    pub(crate) spv_version: (u8, u8),
+    pub(crate) writer_flags: WriterFlags,
+    pub(crate) bounds_check_policies: BoundsCheckPolicies,
+    pub(crate) entry_point: Option<String>,
+    pub(crate) target: Target,
+    pub(crate) disk_cache: Option<DiskCache>,
+}
+
+impl CompileOptions {
+    /// Add a directory to search when resolving `#include` directives.
+    ///
+    /// Directories are searched in the order in which they were added.
+    pub fn include_dir<P>(mut self, dir: P) -> Self
+    where
+        P: AsRef<Path>,
+    {
+        self.include_dirs.push(dir.as_ref().to_path_buf());
+        self
+    }
+
+    /// Add each of the given directories to search when resolving `#include` directives.
+    pub fn include_dirs<I>(mut self, dirs: I) -> Self
+    where
+        I: IntoIterator,
+        I::Item: AsRef<Path>,
+    {
+        let dirs = dirs.into_iter().map(|dir| dir.as_ref().to_path_buf());
+        self.include_dirs.extend(dirs);
+        self
+    }
+
+    /// The strategy used to determine the stage of a shader file.
+    ///
+    /// Defaults to the `DefaultStageResolver`.
+    pub fn stage_resolver<R>(mut self, resolver: R) -> Self
+    where
+        R: 'static + StageResolver,
+    {
+        self.stage_resolver = Arc::new(resolver);
+        self
+    }
+
+    /// Define the preprocessor macro `name` as `value`, as though by `#define name value`.
+    ///
+    /// An empty value is equivalent to `#define name`.
+    pub fn define<K, V>(mut self, name: K, value: V) -> Self
+    where
+        K: Into<String>,
+        V: Into<String>,
+    {
+        self.defines.insert(name.into(), value.into());
+        self
+    }
+
+    /// Define each of the given `(name, value)` preprocessor macros.
+    pub fn defines<I, K, V>(mut self, defines: I) -> Self
+    where
+        I: IntoIterator<Item = (K, V)>,
+        K: Into<String>,
+        V: Into<String>,
+    {
+        let defines = defines.into_iter().map(|(k, v)| (k.into(), v.into()));
+        self.defines.extend(defines);
+        self
+    }
+
+    /// The checks performed by naga's validator.
+    ///
+    /// Defaults to `ValidationFlags::default()`.
+    pub fn validation_flags(mut self, flags: ValidationFlags) -> Self {
+        self.validation_flags = flags;
+        self
+    }
+
+    /// The capabilities that shaders are permitted to use, e.g. to match those of a target GPU.
+    ///
+    /// Defaults to `Capabilities::all()`.
+    pub fn capabilities(mut self, capabilities: Capabilities) -> Self {
+        self.capabilities = capabilities;
+        self
+    }
+
+    /// The `(major, minor)` SPIR-V language version to target.
+    ///
+    /// Defaults to `(1, 0)`.
+    pub fn spv_version(mut self, major: u8, minor: u8) -> Self {
+        self.spv_version = (major, minor);
+        self
+    }
+
+    /// Flags for the SPIR-V writer, e.g. `WriterFlags::DEBUG` or
+    /// `WriterFlags::ADJUST_COORDINATE_SPACE`.
+    ///
+    /// Defaults to the same flags as naga's default SPIR-V options.
+    pub fn writer_flags(mut self, flags: WriterFlags) -> Self {
+        self.writer_flags = flags;
+        self
+    }
+
+    /// How generated code should handle out-of-bounds indices.
+    ///
+    /// Defaults to `BoundsCheckPolicies::default()`.
+    pub fn bounds_check_policies(mut self, policies: BoundsCheckPolicies) -> Self {
+        self.bounds_check_policies = policies;
+        self
+    }
+
+    /// Only write the entry point with the given name.
+    ///
+    /// By default all entry points within the module are written.
+    pub fn entry_point<S>(mut self, name: S) -> Self
+    where
+        S: Into<String>,
+    {
+        self.entry_point = Some(name.into());
+        self
+    }
+
+    /// The language to which shaders are compiled.
+    ///
+    /// Defaults to `Target::Spirv`. Other targets are enabled via their cargo features.
+    pub fn target(mut self, target: Target) -> Self {
+        self.target = target;
+        self
+    }
+
+    /// Persist compiled shaders within the given on-disk cache.
+    ///
+    /// See `DiskCache` for details. By default no on-disk cache is used.
+    pub fn disk_cache(mut self, cache: DiskCache) -> Self {
+        self.disk_cache = Some(cache);
+        self
+    }
+
+    /// Feed every option that affects the compiled output into the given hasher.
+    ///
+    /// The stage resolver is not included, so callers must account for it separately. The disk
+    /// cache is excluded as it does not affect the output.
+    pub(crate) fn fingerprint<H: std::hash::Hasher>(&self, hasher: &mut H) {
+        use std::hash::Hash;
+        let options = CompileOptions {
+            disk_cache: None,
+            ..self.clone()
+        };
+        format!("{:?}", options).hash(hasher);
+    }
+
+    /// Produce naga's GLSL frontend options for the given stage.
+    pub(crate) fn glsl_options(&self, stage: naga::ShaderStage) -> naga::front::glsl::Options {
+        let mut opts = naga::front::glsl::Options::from(stage);
+        let defines = self.defines.iter().map(|(k, v)| (k.clone(), v.clone()));
+        opts.defines.extend(defines);
+        opts
+    }
+
+    /// Produce naga's SPIR-V writer options.
+    pub(crate) fn spv_options(&self) -> naga::back::spv::Options<'static> {
+        naga::back::spv::Options {
+            lang_version: self.spv_version,
+            flags: self.writer_flags,
+            bounds_check_policies: self.bounds_check_policies,
+            ..Default::default()
+        }
+    }
+
+    /// Produce naga's SPIR-V pipeline options for the given stage if an entry point was specified.
+    pub(crate) fn spv_pipeline_options(
+        &self,
+        stage: naga::ShaderStage,
+    ) -> Option<naga::back::spv::PipelineOptions> {
+        self.entry_point
+            .clone()
+            .map(|entry_point| naga::back::spv::PipelineOptions {
+                shader_stage: stage,
+                entry_point,
+            })
+    }
+}
+
+impl Default for CompileOptions {
+    fn default() -> Self {
+        let spv = naga::back::spv::Options::default();
+        CompileOptions {
+            include_dirs: vec![],
+            stage_resolver: Arc::new(DefaultStageResolver),
+            defines: BTreeMap::new(),
+            validation_flags: ValidationFlags::default(),
+            capabilities: Capabilities::all(),
+            spv_version: spv.lang_version,
+            writer_flags: spv.flags,
+            bounds_check_policies: spv.bounds_check_policies,
+            entry_point: None,
+            target: Target::default(),
+            disk_cache: None,
+        }
+    }
+}
+
+impl std::fmt::Debug for CompileOptions {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        f.debug_struct("CompileOptions")
+            .field("include_dirs", &self.include_dirs)
+            .field("defines", &self.defines)
+            .field("validation_flags", &self.validation_flags)
+            .field("capabilities", &self.capabilities)
+            .field("spv_version", &self.spv_version)
+            .field("writer_flags", &self.writer_flags)
+            .field("bounds_check_policies", &self.bounds_check_policies)
+            .field("entry_point", &self.entry_point)
+            .field("target", &self.target)
+            .field("disk_cache", &self.disk_cache)
+            .finish_non_exhaustive()
+    }
+}
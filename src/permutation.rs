@@ -0,0 +1,115 @@
+//! Compilation of a single shader source under multiple sets of preprocessor definitions.
+
+use std::collections::BTreeMap;
+
+/// The raw SPIR-V bytes of each permutation of a shader.
+pub type CompiledPermutations = BTreeMap<PermutationKey, Vec<u8>>;
+
+/// A declared set of shader permutations.
+///
+/// Each axis names a macro and the values it may take. The permutations are the cartesian product
+/// of all axes, e.g. `SHADOWS={0,1} x MSAA={1,4}` produces four permutations. Axes without any
+/// values are ignored, rather than producing no permutations at all.
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct Permutations {
+    axes: Vec<(String, Vec<String>)>,
+}
+
+/// Identifies a single permutation by the value of each macro it defines.
+#[derive(Clone, Debug, Default, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub struct PermutationKey {
+    defines: BTreeMap<String, String>,
+}
+
+impl Permutations {
+    /// An empty set, producing a single permutation that defines nothing.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Add an axis defining the macro `name` as each of the given `values` in turn.
+    ///
+    /// Adding an axis with a name that was already added replaces its values.
+    pub fn axis<S, I>(mut self, name: S, values: I) -> Self
+    where
+        S: Into<String>,
+        I: IntoIterator,
+        I::Item: ToString,
+    {
+        let name = name.into();
+        let values = values.into_iter().map(|v| v.to_string()).collect();
+        match self.axes.iter_mut().find(|(n, _)| *n == name) {
+            Some((_, vs)) => *vs = values,
+            None => self.axes.push((name, values)),
+        }
+        self
+    }
+
+    /// The key of every permutation within the set.
+    pub fn keys(&self) -> Vec<PermutationKey> {
+        let mut keys = vec![PermutationKey::default()];
+        for (name, values) in self.axes.iter().filter(|(_, values)| !values.is_empty()) {
+            keys = keys
+                .iter()
+                .flat_map(|key| {
+                    values.iter().map(move |value| {
+                        let mut key = key.clone();
+                        key.defines.insert(name.clone(), value.clone());
+                        key
+                    })
+                })
+                .collect();
+        }
+        keys
+    }
+}
+
+impl PermutationKey {
+    /// The macro definitions that make up this permutation.
+    pub fn defines(&self) -> &BTreeMap<String, String> {
+        &self.defines
+    }
+}
+
+impl std::fmt::Display for PermutationKey {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        for (ix, (name, value)) in self.defines.iter().enumerate() {
+            if ix > 0 {
+                write!(f, ",")?;
+            }
+            write!(f, "{}={}", name, value)?;
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn keys_are_the_product_of_all_axes() {
+        let permutations = Permutations::new()
+            .axis("SHADOWS", 0..2)
+            .axis("MSAA", [1, 4]);
+        let keys: Vec<_> = permutations.keys().iter().map(|k| k.to_string()).collect();
+        let expected = [
+            "MSAA=1,SHADOWS=0",
+            "MSAA=4,SHADOWS=0",
+            "MSAA=1,SHADOWS=1",
+            "MSAA=4,SHADOWS=1",
+        ];
+        assert_eq!(keys, expected);
+    }
+
+    #[test]
+    fn empty_axes_are_ignored() {
+        let empty: [u32; 0] = [];
+        let permutations = Permutations::new().axis("A", 0..2).axis("B", empty);
+        assert_eq!(
+            permutations.keys(),
+            Permutations::new().axis("A", 0..2).keys()
+        );
+        assert_eq!(Permutations::new().axis("B", empty).keys().len(), 1);
+    }
+}
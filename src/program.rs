@@ -0,0 +1,209 @@
+//! Groups of shader stages that are linked together into a pipeline.
+//!
+//! Compiling a `Program` verifies that the outputs of its vertex stage match the inputs of its
+//! fragment stage, catching mismatches that would otherwise only be reported by the driver.
+
+use crate::cache::CompileCache;
+use crate::reflect::{InterfaceType, Reflection};
+use crate::{CompileOptions, CompiledShader, Diagnostic, ScalarKind};
+use std::collections::HashMap;
+use std::fmt;
+use std::path::{Path, PathBuf};
+use std::sync::Mutex;
+
+/// A vertex and fragment shader pair that are linked together.
+#[derive(Clone, Debug, PartialEq, Eq, Hash)]
+pub struct Program {
+    pub vertex: PathBuf,
+    pub fragment: PathBuf,
+}
+
+/// The compiled stages of a `Program`.
+#[derive(Clone, Debug, PartialEq)]
+pub struct CompiledProgram {
+    pub vertex: CompiledShader,
+    pub fragment: CompiledShader,
+}
+
+/// The interface mismatches found when each program stage was last compiled by a `Watch`.
+#[derive(Debug, Default)]
+pub(crate) struct InterfaceChecks {
+    diagnostics: Mutex<HashMap<PathBuf, Vec<Diagnostic>>>,
+}
+
+impl Program {
+    /// A program consisting of the vertex and fragment shaders at the given paths.
+    pub fn new<V, F>(vertex: V, fragment: F) -> Self
+    where
+        V: AsRef<Path>,
+        F: AsRef<Path>,
+    {
+        Program {
+            vertex: vertex.as_ref().to_path_buf(),
+            fragment: fragment.as_ref().to_path_buf(),
+        }
+    }
+
+    /// Whether or not the shader at the given path is one of the program's stages.
+    pub fn contains(&self, path: &Path) -> bool {
+        let path = crate::include::normalize(path);
+        [&self.vertex, &self.fragment]
+            .iter()
+            .any(|stage| crate::include::normalize(stage) == path)
+    }
+}
+
+/// Check that every input of the fragment stage is written by the vertex stage with a matching
+/// type, interpolation and sampling.
+///
+/// Returns a diagnostic for each mismatch, or an empty `Vec` if the interfaces match.
+pub fn check_interface(
+    vertex: &Reflection,
+    vertex_path: &Path,
+    fragment: &Reflection,
+    fragment_path: &Path,
+) -> Vec<Diagnostic> {
+    let mut diagnostics = vec![];
+    for input in &fragment.inputs {
+        let name = input.name.as_deref().unwrap_or("<unnamed>");
+        let output = vertex
+            .outputs
+            .iter()
+            .find(|output| output.location == input.location);
+        let output = match output {
+            Some(output) => output,
+            None => {
+                let message = format!(
+                    "fragment input `{}` at location {} is not written by the vertex shader",
+                    name, input.location,
+                );
+                let diag = Diagnostic::error(message)
+                    .with_path(Some(fragment_path))
+                    .with_note(format!("vertex shader: {}", vertex_path.display()));
+                diagnostics.push(diag);
+                continue;
+            }
+        };
+        let mut mismatches = vec![];
+        if output.ty != input.ty {
+            mismatches.push(format!(
+                "the vertex shader writes `{}` but the fragment shader reads `{}`",
+                output.ty, input.ty,
+            ));
+        }
+        if output.interpolation != input.interpolation {
+            mismatches.push(format!(
+                "the vertex shader uses {} interpolation but the fragment shader uses {}",
+                describe(output.interpolation),
+                describe(input.interpolation),
+            ));
+        }
+        if output.sampling != input.sampling {
+            mismatches.push(format!(
+                "the vertex shader uses {} sampling but the fragment shader uses {}",
+                describe(output.sampling),
+                describe(input.sampling),
+            ));
+        }
+        if mismatches.is_empty() {
+            continue;
+        }
+        let message = format!(
+            "fragment input `{}` at location {} does not match the vertex output",
+            name, input.location,
+        );
+        let mut diag = Diagnostic::error(message).with_path(Some(fragment_path));
+        for mismatch in mismatches {
+            diag = diag.with_note(mismatch);
+        }
+        let diag = diag.with_note(format!("vertex shader: {}", vertex_path.display()));
+        diagnostics.push(diag);
+    }
+    diagnostics
+}
+
+/// Check the interfaces of each of the given programs of which the shader at the given path is a
+/// stage.
+///
+/// The stages are reflected via the cache without affecting its counters. Programs with a stage
+/// that fails to compile are skipped, as that stage's errors are reported when it is compiled.
+pub(crate) fn check_stage(
+    programs: &[Program],
+    path: &Path,
+    options: &CompileOptions,
+    cache: &CompileCache,
+) -> Vec<Diagnostic> {
+    let mut diagnostics = vec![];
+    for program in programs.iter().filter(|p| p.contains(path)) {
+        let vertex = match cache.reflect(&program.vertex, options) {
+            Ok(vertex) => vertex,
+            Err(_) => continue,
+        };
+        let fragment = match cache.reflect(&program.fragment, options) {
+            Ok(fragment) => fragment,
+            Err(_) => continue,
+        };
+        diagnostics.extend(check_interface(
+            &vertex,
+            &program.vertex,
+            &fragment,
+            &program.fragment,
+        ));
+    }
+    diagnostics
+}
+
+impl InterfaceChecks {
+    /// The mismatches found when the shader at the given path was last compiled.
+    pub(crate) fn get(&self, path: &Path) -> Vec<Diagnostic> {
+        let diagnostics = self.diagnostics.lock().unwrap();
+        diagnostics.get(path).cloned().unwrap_or_default()
+    }
+
+    /// Record the mismatches found when compiling the shader at the given path.
+    pub(crate) fn record(&self, path: &Path, found: Vec<Diagnostic>) {
+        let mut diagnostics = self.diagnostics.lock().unwrap();
+        if found.is_empty() {
+            diagnostics.remove(path);
+        } else {
+            diagnostics.insert(path.to_path_buf(), found);
+        }
+    }
+
+    /// Forget the mismatches of the stages of the given program.
+    pub(crate) fn forget(&self, program: &Program) {
+        let mut diagnostics = self.diagnostics.lock().unwrap();
+        diagnostics.retain(|path, _| !program.contains(path));
+    }
+
+    /// Forget the mismatches of all shaders.
+    pub(crate) fn clear(&self) {
+        self.diagnostics.lock().unwrap().clear();
+    }
+}
+
+/// Describe an optional interpolation or sampling qualifier, e.g. `flat` or `default`.
+fn describe<T: fmt::Debug>(qualifier: Option<T>) -> String {
+    match qualifier {
+        Some(q) => format!("`{}`", format!("{:?}", q).to_lowercase()),
+        None => "default".to_string(),
+    }
+}
+
+impl fmt::Display for InterfaceType {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let (scalar, prefix) = match (self.kind, self.width) {
+            (ScalarKind::Float, 8) => ("double", "d"),
+            (ScalarKind::Float, _) => ("float", ""),
+            (ScalarKind::Sint, _) => ("int", "i"),
+            (ScalarKind::Uint, _) => ("uint", "u"),
+            (ScalarKind::Bool, _) => ("bool", "b"),
+        };
+        match (self.columns, self.components) {
+            (1, 1) => write!(f, "{}", scalar),
+            (1, n) => write!(f, "{}vec{}", prefix, n),
+            (c, r) if c == r => write!(f, "{}mat{}", prefix, c),
+            (c, r) => write!(f, "{}mat{}x{}", prefix, c, r),
+        }
+    }
+}
@@ -0,0 +1,512 @@
+//! Reflection of the resources and interface of a compiled shader.
+//!
+//! See `CompiledShader` and the `compile_reflected` function.
+
+use crate::ShaderStage;
+pub use naga::{ImageDimension, Interpolation, Sampling, ScalarKind, StorageFormat};
+
+/// A compiled shader along with reflection information gathered from its naga module.
+#[derive(Clone, Debug, PartialEq)]
+pub struct CompiledShader {
+    /// The raw SPIR-V bytes, or the UTF-8 encoded source for text targets. See `Target`.
+    pub bytes: Vec<u8>,
+    /// The resources and interface of the shader's entry point.
+    pub reflection: Reflection,
+}
+
+/// The resources and interface of a shader's entry point.
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct Reflection {
+    /// The name of the entry point.
+    pub entry_point: String,
+    /// The stage of the entry point, or `None` if the module had no matching entry point.
+    pub stage: Option<ShaderStage>,
+    /// All resources bound via `layout(set = ..., binding = ...)`, ordered by group and binding.
+    pub bindings: Vec<ResourceBinding>,
+    /// All push constant blocks.
+    pub push_constants: Vec<PushConstantRange>,
+    /// Location-bound inputs to the entry point, ordered by location. Built-ins are excluded.
+    pub inputs: Vec<InterfaceVariable>,
+    /// Location-bound outputs of the entry point, ordered by location. Built-ins are excluded.
+    pub outputs: Vec<InterfaceVariable>,
+    /// The workgroup size of a compute entry point.
+    pub workgroup_size: Option<[u32; 3]>,
+}
+
+/// A resource bound to a group and binding.
+#[derive(Clone, Debug, PartialEq, Eq, Hash)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct ResourceBinding {
+    pub name: Option<String>,
+    pub group: u32,
+    pub binding: u32,
+    pub ty: BindingType,
+    pub access: ResourceAccess,
+    /// The number of elements if the resource is a binding array, or `None` if it is not an array
+    /// or the array is unsized.
+    pub count: Option<u32>,
+}
+
+/// The type of a bound resource.
+#[derive(Clone, Debug, PartialEq, Eq, Hash)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub enum BindingType {
+    UniformBuffer {
+        size: u32,
+    },
+    StorageBuffer {
+        /// The minimum size of the buffer, i.e. excluding any runtime-sized array elements.
+        size: u32,
+    },
+    SampledTexture {
+        dim: ImageDimension,
+        arrayed: bool,
+        kind: ScalarKind,
+        multisampled: bool,
+    },
+    DepthTexture {
+        dim: ImageDimension,
+        arrayed: bool,
+        multisampled: bool,
+    },
+    StorageTexture {
+        dim: ImageDimension,
+        arrayed: bool,
+        format: StorageFormat,
+    },
+    Sampler {
+        comparison: bool,
+    },
+    AccelerationStructure,
+}
+
+/// How a shader may access a bound resource.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub enum ResourceAccess {
+    ReadOnly,
+    WriteOnly,
+    ReadWrite,
+}
+
+/// A push constant block.
+#[derive(Clone, Debug, PartialEq, Eq, Hash)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct PushConstantRange {
+    pub name: Option<String>,
+    pub size: u32,
+}
+
+/// A location-bound input or output of an entry point.
+#[derive(Clone, Debug, PartialEq, Eq, Hash)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct InterfaceVariable {
+    pub name: Option<String>,
+    pub location: u32,
+    pub ty: InterfaceType,
+    pub interpolation: Option<Interpolation>,
+    pub sampling: Option<Sampling>,
+}
+
+/// The format of an interface variable, e.g. a `vec3` is a `Float` of width 4 with 3 components.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct InterfaceType {
+    pub kind: ScalarKind,
+    /// The width of each component in bytes.
+    pub width: u8,
+    /// The number of components, from 1 to 4.
+    pub components: u8,
+    /// The number of columns for matrices, or 1 otherwise.
+    pub columns: u8,
+}
+
+impl Reflection {
+    /// Reflect the entry point within the module matching the given stage and optional name.
+    pub(crate) fn new(
+        module: &naga::Module,
+        stage: ShaderStage,
+        entry_point: Option<&str>,
+    ) -> Self {
+        let mut reflection = Reflection::default();
+        reflect_globals(module, &mut reflection);
+        let ep = module
+            .entry_points
+            .iter()
+            .filter(|ep| ep.stage == stage)
+            .find(|ep| entry_point.map(|name| ep.name == name).unwrap_or(true));
+        let ep = match ep {
+            None => return reflection,
+            Some(ep) => ep,
+        };
+        reflection.entry_point = ep.name.clone();
+        reflection.stage = Some(ep.stage);
+        if let ShaderStage::Compute = ep.stage {
+            reflection.workgroup_size = Some(ep.workgroup_size);
+        }
+        for arg in &ep.function.arguments {
+            let name = arg.name.as_deref();
+            interface(
+                module,
+                name,
+                arg.ty,
+                arg.binding.as_ref(),
+                &mut reflection.inputs,
+            );
+        }
+        if let Some(ref result) = ep.function.result {
+            interface(
+                module,
+                None,
+                result.ty,
+                result.binding.as_ref(),
+                &mut reflection.outputs,
+            );
+        }
+        reflection.inputs.sort_by_key(|var| var.location);
+        reflection.outputs.sort_by_key(|var| var.location);
+        reflection
+    }
+
+    /// The inputs of a vertex entry point.
+    pub fn vertex_inputs(&self) -> &[InterfaceVariable] {
+        match self.stage {
+            Some(ShaderStage::Vertex) => &self.inputs,
+            _ => &[],
+        }
+    }
+
+    /// The outputs of a fragment entry point.
+    pub fn fragment_outputs(&self) -> &[InterfaceVariable] {
+        match self.stage {
+            Some(ShaderStage::Fragment) => &self.outputs,
+            _ => &[],
+        }
+    }
+}
+
+impl InterfaceType {
+    fn from_inner(inner: &naga::TypeInner) -> Option<Self> {
+        let ty = match *inner {
+            naga::TypeInner::Scalar { kind, width } => InterfaceType {
+                kind,
+                width,
+                components: 1,
+                columns: 1,
+            },
+            naga::TypeInner::Vector { size, kind, width } => InterfaceType {
+                kind,
+                width,
+                components: size as u8,
+                columns: 1,
+            },
+            naga::TypeInner::Matrix {
+                columns,
+                rows,
+                width,
+            } => InterfaceType {
+                kind: ScalarKind::Float,
+                width,
+                components: rows as u8,
+                columns: columns as u8,
+            },
+            _ => return None,
+        };
+        Some(ty)
+    }
+}
+
+/// Collect the resource bindings and push constants declared within the module.
+fn reflect_globals(module: &naga::Module, reflection: &mut Reflection) {
+    let gctx = module.to_ctx();
+    for (_, var) in module.global_variables.iter() {
+        let name = var.name.clone();
+        let inner = &module.types[var.ty].inner;
+        if let naga::AddressSpace::PushConstant = var.space {
+            let size = inner.size(gctx);
+            reflection
+                .push_constants
+                .push(PushConstantRange { name, size });
+            continue;
+        }
+        let binding = match var.binding {
+            Some(ref binding) => binding,
+            None => continue,
+        };
+        let (inner, count) = match *inner {
+            naga::TypeInner::BindingArray { base, size } => {
+                let count = match size {
+                    naga::ArraySize::Constant(n) => Some(n.get()),
+                    naga::ArraySize::Dynamic => None,
+                };
+                (&module.types[base].inner, count)
+            }
+            _ => (inner, None),
+        };
+        let (ty, access) = match (var.space, inner) {
+            (naga::AddressSpace::Uniform, _) => {
+                let size = inner.size(gctx);
+                (
+                    BindingType::UniformBuffer { size },
+                    ResourceAccess::ReadOnly,
+                )
+            }
+            (naga::AddressSpace::Storage { access }, _) => {
+                let size = inner.size(gctx);
+                (BindingType::StorageBuffer { size }, storage_access(access))
+            }
+            (
+                _,
+                &naga::TypeInner::Image {
+                    dim,
+                    arrayed,
+                    class,
+                },
+            ) => match class {
+                naga::ImageClass::Sampled { kind, multi } => {
+                    let ty = BindingType::SampledTexture {
+                        dim,
+                        arrayed,
+                        kind,
+                        multisampled: multi,
+                    };
+                    (ty, ResourceAccess::ReadOnly)
+                }
+                naga::ImageClass::Depth { multi } => {
+                    let ty = BindingType::DepthTexture {
+                        dim,
+                        arrayed,
+                        multisampled: multi,
+                    };
+                    (ty, ResourceAccess::ReadOnly)
+                }
+                naga::ImageClass::Storage { format, access } => {
+                    let ty = BindingType::StorageTexture {
+                        dim,
+                        arrayed,
+                        format,
+                    };
+                    (ty, storage_access(access))
+                }
+            },
+            (_, &naga::TypeInner::Sampler { comparison }) => {
+                let ty = BindingType::Sampler { comparison };
+                (ty, ResourceAccess::ReadOnly)
+            }
+            (_, &naga::TypeInner::AccelerationStructure) => {
+                let ty = BindingType::AccelerationStructure;
+                (ty, ResourceAccess::ReadOnly)
+            }
+            _ => continue,
+        };
+        reflection.bindings.push(ResourceBinding {
+            name,
+            group: binding.group,
+            binding: binding.binding,
+            ty,
+            access,
+            count,
+        });
+    }
+    reflection
+        .bindings
+        .sort_by_key(|binding| (binding.group, binding.binding));
+}
+
+/// Collect the location-bound interface variables for an entry point argument or result.
+///
+/// Struct types are flattened into their members.
+fn interface(
+    module: &naga::Module,
+    name: Option<&str>,
+    ty: naga::Handle<naga::Type>,
+    binding: Option<&naga::Binding>,
+    vars: &mut Vec<InterfaceVariable>,
+) {
+    let inner = &module.types[ty].inner;
+    if let naga::TypeInner::Struct { ref members, .. } = *inner {
+        for member in members {
+            let name = member.name.as_deref();
+            interface(module, name, member.ty, member.binding.as_ref(), vars);
+        }
+        return;
+    }
+    if let Some(&naga::Binding::Location {
+        location,
+        interpolation,
+        sampling,
+        ..
+    }) = binding
+    {
+        if let Some(ty) = InterfaceType::from_inner(inner) {
+            vars.push(InterfaceVariable {
+                name: name.map(str::to_string),
+                location,
+                ty,
+                interpolation,
+                sampling,
+            });
+        }
+    }
+}
+
+fn storage_access(access: naga::StorageAccess) -> ResourceAccess {
+    let load = access.contains(naga::StorageAccess::LOAD);
+    let store = access.contains(naga::StorageAccess::STORE);
+    match (load, store) {
+        (true, false) => ResourceAccess::ReadOnly,
+        (false, true) => ResourceAccess::WriteOnly,
+        _ => ResourceAccess::ReadWrite,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::CompileOptions;
+
+    fn reflect(source: &str, stage: ShaderStage) -> Reflection {
+        let options = CompileOptions::default();
+        crate::compile_str_reflected(source, stage, &options)
+            .unwrap()
+            .reflection
+    }
+
+    fn binding(reflection: &Reflection, name: &str) -> ResourceBinding {
+        let binding = reflection
+            .bindings
+            .iter()
+            .find(|b| b.name.as_deref() == Some(name));
+        binding.unwrap().clone()
+    }
+
+    #[test]
+    fn buffers_images_and_push_constants_are_reflected() {
+        let source = "#version 450
+layout(local_size_x = 8, local_size_y = 4) in;
+layout(set = 0, binding = 0) uniform Globals { vec4 tint; mat4 transform; } globals;
+layout(set = 0, binding = 1) readonly buffer Items { vec4 items[]; } inputs;
+layout(set = 1, binding = 0) buffer Counter { uint count; } counter;
+layout(set = 2, binding = 0, rgba8) uniform writeonly image2D image;
+layout(push_constant) uniform Push { vec2 offset; float scale; } push;
+void main() {
+    counter.count += uint(inputs.items[0].x + globals.tint.x + push.scale);
+    imageStore(image, ivec2(0), globals.transform[0]);
+}
+";
+        let reflection = reflect(source, ShaderStage::Compute);
+        assert_eq!(reflection.stage, Some(ShaderStage::Compute));
+        assert_eq!(reflection.workgroup_size, Some([8, 4, 1]));
+
+        let globals = binding(&reflection, "globals");
+        assert_eq!((globals.group, globals.binding), (0, 0));
+        assert_eq!(globals.ty, BindingType::UniformBuffer { size: 80 });
+        assert_eq!(globals.access, ResourceAccess::ReadOnly);
+
+        // The runtime-sized array contributes a single element to the minimum size.
+        let inputs = binding(&reflection, "inputs");
+        assert_eq!(inputs.ty, BindingType::StorageBuffer { size: 16 });
+        assert_eq!(inputs.access, ResourceAccess::ReadOnly);
+
+        let counter = binding(&reflection, "counter");
+        assert_eq!((counter.group, counter.binding), (1, 0));
+        assert_eq!(counter.ty, BindingType::StorageBuffer { size: 4 });
+        assert_eq!(counter.access, ResourceAccess::ReadWrite);
+
+        let image = binding(&reflection, "image");
+        let ty = BindingType::StorageTexture {
+            dim: ImageDimension::D2,
+            arrayed: false,
+            format: StorageFormat::Rgba8Unorm,
+        };
+        assert_eq!(image.ty, ty);
+        assert_eq!(image.access, ResourceAccess::WriteOnly);
+        assert_eq!(image.count, None);
+
+        let push = PushConstantRange {
+            name: Some("push".to_string()),
+            size: 16,
+        };
+        assert_eq!(reflection.push_constants, vec![push]);
+    }
+
+    #[test]
+    fn interface_structs_are_flattened() {
+        let source = "#version 450
+layout(location = 1) in uvec4 joints;
+layout(location = 0) in vec2 position;
+layout(location = 0) out vec2 uv;
+layout(location = 1) flat out uint id;
+void main() {
+    uv = position;
+    id = joints.x;
+    gl_Position = vec4(position, 0.0, 1.0);
+}
+";
+        let reflection = reflect(source, ShaderStage::Vertex);
+        assert_eq!(reflection.workgroup_size, None);
+        let inputs: Vec<_> = reflection
+            .vertex_inputs()
+            .iter()
+            .map(|v| v.location)
+            .collect();
+        assert_eq!(inputs, vec![0, 1]);
+        assert!(reflection.fragment_outputs().is_empty());
+
+        // The outputs are members of the entry point's result, while `gl_Position` is a builtin.
+        let outputs = &reflection.outputs;
+        assert_eq!(outputs.len(), 2);
+        assert_eq!(outputs[0].name.as_deref(), Some("uv"));
+        let vec2 = InterfaceType {
+            kind: ScalarKind::Float,
+            width: 4,
+            components: 2,
+            columns: 1,
+        };
+        assert_eq!(outputs[0].ty, vec2);
+        assert_eq!(outputs[1].name.as_deref(), Some("id"));
+        assert_eq!(outputs[1].ty.kind, ScalarKind::Uint);
+        assert_eq!(outputs[1].interpolation, Some(Interpolation::Flat));
+    }
+
+    #[cfg(feature = "wgsl-in")]
+    #[test]
+    fn binding_arrays_are_reflected() {
+        let source = "
+@group(0) @binding(0) var textures: binding_array<texture_2d<f32>, 4>;
+@group(0) @binding(1) var samp: sampler;
+
+struct Out {
+    @location(0) color: vec4<f32>,
+    @location(1) extra: vec4<f32>,
+}
+
+@fragment
+fn main() -> Out {
+    let color = textureSample(textures[1], samp, vec2(0.0));
+    return Out(color, color);
+}
+";
+        let module = naga::front::wgsl::parse_str(source).unwrap();
+        let reflection = Reflection::new(&module, ShaderStage::Fragment, None);
+        let textures = binding(&reflection, "textures");
+        assert_eq!(textures.count, Some(4));
+        let ty = BindingType::SampledTexture {
+            dim: ImageDimension::D2,
+            arrayed: false,
+            kind: ScalarKind::Float,
+            multisampled: false,
+        };
+        assert_eq!(textures.ty, ty);
+        let samp = binding(&reflection, "samp");
+        assert_eq!(samp.ty, BindingType::Sampler { comparison: false });
+        assert_eq!(samp.count, None);
+        let outputs: Vec<_> = reflection
+            .fragment_outputs()
+            .iter()
+            .map(|v| v.name.as_deref())
+            .collect();
+        assert_eq!(outputs, vec![Some("color"), Some("extra")]);
+    }
+}
@@ -0,0 +1,157 @@
+//! Strategies for determining the stage of a shader prior to compilation.
+
+use crate::{ext_to_shader_ty, ShaderStage};
+use std::path::Path;
+
+/// A strategy for determining the stage of the shader at the given path.
+///
+/// The `source` is the shader's GLSL with all `#include` directives expanded, its WGSL source, or
+/// empty for SPIR-V.
+///
+/// A `ShaderStage` is itself a `StageResolver` that always resolves to that stage, useful for
+/// explicitly overriding the stage of a shader.
+pub trait StageResolver: Send + Sync {
+    /// Determine the stage of the shader, or `None` if it could not be determined.
+    fn resolve_stage(&self, path: &Path, source: &str) -> Option<ShaderStage>;
+}
+
+/// Resolves the stage from the path's extension, e.g. `foo.frag`.
+///
+/// See `GLSL_EXTENSIONS` and `ext_to_shader_ty`.
+#[derive(Clone, Copy, Debug, Default)]
+pub struct StageFromExtension;
+
+/// Resolves the stage from the extension preceding the path's final extension, e.g.
+/// `foo.frag.glsl`.
+#[derive(Clone, Copy, Debug, Default)]
+pub struct StageFromCompoundExtension;
+
+/// Resolves the stage from a `#pragma shader_stage(<stage>)` directive within the source, where
+/// `<stage>` is one of `vertex`, `fragment` or `compute`.
+#[derive(Clone, Copy, Debug, Default)]
+pub struct StageFromPragma;
+
+/// The resolver used by default.
+///
+/// Tries a `#pragma shader_stage(...)` directive first, followed by the path's extension and
+/// finally its compound extension.
+#[derive(Clone, Copy, Debug, Default)]
+pub struct DefaultStageResolver;
+
+impl StageResolver for StageFromExtension {
+    fn resolve_stage(&self, path: &Path, _source: &str) -> Option<ShaderStage> {
+        path.extension()
+            .and_then(|s| s.to_str())
+            .and_then(ext_to_shader_ty)
+    }
+}
+
+impl StageResolver for StageFromCompoundExtension {
+    fn resolve_stage(&self, path: &Path, _source: &str) -> Option<ShaderStage> {
+        path.file_stem()
+            .map(Path::new)
+            .and_then(Path::extension)
+            .and_then(|s| s.to_str())
+            .and_then(ext_to_shader_ty)
+    }
+}
+
+impl StageResolver for StageFromPragma {
+    fn resolve_stage(&self, _path: &Path, source: &str) -> Option<ShaderStage> {
+        source.lines().find_map(|line| {
+            let rest = line.trim_start().strip_prefix('#')?.trim_start();
+            let rest = rest.strip_prefix("pragma")?.trim_start();
+            let rest = rest.strip_prefix("shader_stage")?.trim_start();
+            let stage = rest.strip_prefix('(')?.split(')').next()?.trim();
+            match stage {
+                "vertex" => Some(ShaderStage::Vertex),
+                "fragment" => Some(ShaderStage::Fragment),
+                "compute" => Some(ShaderStage::Compute),
+                _ => None,
+            }
+        })
+    }
+}
+
+impl StageResolver for DefaultStageResolver {
+    fn resolve_stage(&self, path: &Path, source: &str) -> Option<ShaderStage> {
+        StageFromPragma
+            .resolve_stage(path, source)
+            .or_else(|| StageFromExtension.resolve_stage(path, source))
+            .or_else(|| StageFromCompoundExtension.resolve_stage(path, source))
+    }
+}
+
+impl StageResolver for ShaderStage {
+    fn resolve_stage(&self, _path: &Path, _source: &str) -> Option<ShaderStage> {
+        Some(*self)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::common::TempDir;
+    use crate::CompileError;
+
+    const SHADER: &str = "#version 450\nvoid main() {}\n";
+
+    #[test]
+    fn pragma_names_the_stage() {
+        let path = Path::new("shader.glsl");
+        let resolve = |source| StageFromPragma.resolve_stage(path, source);
+        let source = "#version 450\n  #  pragma shader_stage( compute )\n";
+        assert_eq!(resolve(source), Some(ShaderStage::Compute));
+        assert_eq!(
+            resolve("#pragma shader_stage(vertex)"),
+            Some(ShaderStage::Vertex)
+        );
+        assert_eq!(resolve("#pragma shader_stage(geometry)"), None);
+        assert_eq!(resolve("#pragma once"), None);
+        assert_eq!(resolve(SHADER), None);
+    }
+
+    #[test]
+    fn compound_extension_names_the_stage() {
+        let resolve = |path| StageFromCompoundExtension.resolve_stage(Path::new(path), "");
+        assert_eq!(resolve("foo.frag.glsl"), Some(ShaderStage::Fragment));
+        assert_eq!(
+            resolve("dir.vert/foo.comp.glsl"),
+            Some(ShaderStage::Compute)
+        );
+        assert_eq!(resolve("foo.frag"), None);
+        assert_eq!(resolve("foo.glsl"), None);
+    }
+
+    #[test]
+    fn default_resolver_prefers_pragma_then_extension_then_compound_extension() {
+        let resolve = |path, source| DefaultStageResolver.resolve_stage(Path::new(path), source);
+        let pragma = "#pragma shader_stage(compute)\n";
+        assert_eq!(resolve("foo.vert.frag", pragma), Some(ShaderStage::Compute));
+        assert_eq!(resolve("foo.vert.frag", ""), Some(ShaderStage::Fragment));
+        assert_eq!(resolve("foo.vert.glsl", ""), Some(ShaderStage::Vertex));
+        assert_eq!(resolve("foo.glsl", ""), None);
+    }
+
+    #[test]
+    fn shaders_are_compiled_with_the_resolved_stage() {
+        let dir = TempDir::new("stage-compile");
+        let compound = dir.join("foo.frag.glsl");
+        std::fs::write(&compound, SHADER).unwrap();
+        let shader = crate::compile_reflected(&compound, &Default::default()).unwrap();
+        assert_eq!(shader.reflection.stage, Some(ShaderStage::Fragment));
+
+        let unknown = dir.join("foo.glsl");
+        std::fs::write(&unknown, SHADER).unwrap();
+        match crate::compile(&unknown) {
+            Err(CompileError::UnresolvedStage { path }) => assert_eq!(path, unknown),
+            other => panic!("expected an unresolved stage, got {:?}", other),
+        }
+
+        // An explicit stage overrides the extension.
+        let resolver = ShaderStage::Compute;
+        let options = crate::CompileOptions::default().stage_resolver(resolver);
+        let shader = crate::compile_reflected(&unknown, &options).unwrap();
+        assert_eq!(shader.reflection.stage, Some(ShaderStage::Compute));
+    }
+}
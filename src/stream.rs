@@ -0,0 +1,177 @@
+//! An async API for awaiting shader changes, enabled by the `async` feature.
+//!
+//! Futures are woken directly by the notify event handler, so no particular runtime is required.
+
+use crate::{CompiledPath, NextPathError, Watch};
+use futures_core::Stream;
+use std::path::PathBuf;
+use std::pin::Pin;
+use std::sync::{Arc, Mutex};
+use std::task::{Context, Poll, Waker};
+use std::time::{Duration, Instant};
+
+/// The wakers of all tasks awaiting events from a `Watch`.
+///
+/// Shared with the notify event handler, which wakes every registered task upon each event.
+#[derive(Debug, Default)]
+pub(crate) struct EventWakers {
+    wakers: Mutex<Vec<Waker>>,
+    /// The instant at which the pending timer thread will wake all tasks, if any.
+    deadline: Mutex<Option<Instant>>,
+}
+
+/// A stream that compiles each touched shader. See `Watch::into_compile_stream`.
+///
+/// Dropping the stream drops the `Watch` along with its underlying notify watcher.
+pub struct CompileStream {
+    watch: Watch,
+}
+
+impl EventWakers {
+    /// Register the waker of a task awaiting the next event.
+    fn register(&self, waker: &Waker) {
+        let mut wakers = self.wakers.lock().unwrap();
+        if !wakers.iter().any(|w| w.will_wake(waker)) {
+            wakers.push(waker.clone());
+        }
+    }
+
+    /// Wake all registered tasks.
+    pub(crate) fn wake(&self) {
+        for waker in self.wakers.lock().unwrap().drain(..) {
+            waker.wake();
+        }
+    }
+
+    /// Wake all registered tasks once the given duration has elapsed.
+    ///
+    /// A timer thread is only spawned if no pending timer would fire by then, so at most one
+    /// timer is pending for a burst of events that keeps pushing the deadline back.
+    fn wake_after(self: &Arc<Self>, timeout: Duration) {
+        let deadline = Instant::now() + timeout;
+        {
+            let mut pending = self.deadline.lock().unwrap();
+            if pending.is_some_and(|pending| pending <= deadline) {
+                return;
+            }
+            *pending = Some(deadline);
+        }
+        let wakers = self.clone();
+        std::thread::spawn(move || {
+            std::thread::sleep(deadline.saturating_duration_since(Instant::now()));
+            {
+                let mut pending = wakers.deadline.lock().unwrap();
+                if *pending == Some(deadline) {
+                    *pending = None;
+                }
+            }
+            wakers.wake();
+        });
+    }
+}
+
+impl Watch {
+    /// Asynchronously wait for the next touched shader path.
+    ///
+    /// This is the async equivalent of polling `try_next_path` until it yields a path. If a
+    /// debounce window has been set, a timer thread wakes the task once the window has elapsed.
+    /// A single timer is shared by all tasks awaiting the `Watch`.
+    pub async fn next_event(&self) -> Result<PathBuf, NextPathError> {
+        std::future::poll_fn(|cx| self.poll_next_path(cx)).await
+    }
+
+    /// Convert the `Watch` into a stream that compiles each touched shader with the `Watch`'s
+    /// `CompileOptions`.
+    ///
+    /// Compilation occurs synchronously within the stream's `poll_next`. Errors reported by notify
+    /// are skipped. The stream owns the notify watcher via the `Watch` and so never ends; drop it
+    /// or call `into_watch` to stop compiling.
+    pub fn into_compile_stream(self) -> CompileStream {
+        CompileStream { watch: self }
+    }
+
+    /// Poll for the next touched path, registering the task's waker if there is none.
+    fn poll_next_path(&self, cx: &mut Context) -> Poll<Result<PathBuf, NextPathError>> {
+        // Register before checking to avoid missing an event that arrives in between.
+        self.wakers.register(cx.waker());
+        match self.try_next_path() {
+            Ok(Some(path)) => return Poll::Ready(Ok(path)),
+            Err(err) => return Poll::Ready(Err(err)),
+            Ok(None) => (),
+        }
+        let until_settled = self.events.lock().unwrap().debouncer.time_until_settled();
+        if let Some(timeout) = until_settled {
+            self.wakers.wake_after(timeout);
+        }
+        Poll::Pending
+    }
+}
+
+impl CompileStream {
+    /// The `Watch` from which touched paths are received.
+    pub fn watch(&self) -> &Watch {
+        &self.watch
+    }
+
+    /// Convert the stream back into its `Watch`.
+    pub fn into_watch(self) -> Watch {
+        self.watch
+    }
+}
+
+impl Stream for CompileStream {
+    type Item = CompiledPath;
+    fn poll_next(self: Pin<&mut Self>, cx: &mut Context) -> Poll<Option<Self::Item>> {
+        loop {
+            match self.watch.poll_next_path(cx) {
+                Poll::Pending => return Poll::Pending,
+                Poll::Ready(Err(NextPathError::ChannelClosed)) => return Poll::Ready(None),
+                Poll::Ready(Err(NextPathError::Notify { .. })) => continue,
+                Poll::Ready(Ok(path)) => {
+                    let result = self.watch.compile_cached(&path);
+                    return Poll::Ready(Some((path, result)));
+                }
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use futures::task::ArcWake;
+    use std::sync::atomic::{AtomicUsize, Ordering};
+
+    #[derive(Default)]
+    struct CountingWaker(AtomicUsize);
+
+    impl ArcWake for CountingWaker {
+        fn wake_by_ref(arc_self: &Arc<Self>) {
+            arc_self.0.fetch_add(1, Ordering::SeqCst);
+        }
+    }
+
+    #[test]
+    fn burst_shares_a_single_timer() {
+        let wakers = Arc::new(EventWakers::default());
+        wakers.wake_after(Duration::from_millis(50));
+        let deadline = *wakers.deadline.lock().unwrap();
+        for ms in 51..100 {
+            wakers.wake_after(Duration::from_millis(ms));
+        }
+        assert_eq!(*wakers.deadline.lock().unwrap(), deadline);
+        wakers.wake_after(Duration::from_millis(10));
+        assert!(*wakers.deadline.lock().unwrap() < deadline);
+    }
+
+    #[test]
+    fn timer_wakes_registered_tasks() {
+        let wakers = Arc::new(EventWakers::default());
+        let counter = Arc::new(CountingWaker::default());
+        wakers.register(&futures::task::waker(counter.clone()));
+        wakers.wake_after(Duration::from_millis(10));
+        std::thread::sleep(Duration::from_millis(200));
+        assert_eq!(counter.0.load(Ordering::SeqCst), 1);
+        assert_eq!(*wakers.deadline.lock().unwrap(), None);
+    }
+}
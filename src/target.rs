@@ -0,0 +1,118 @@
+//! The output languages that shaders may be compiled to.
+//!
+//! SPIR-V is always available. Each other target is enabled by the cargo feature of the same name,
+//! e.g. `wgsl`, which enables the corresponding naga backend.
+
+use crate::{CompileOptions, NagaError, ShaderStage};
+#[cfg(feature = "glsl")]
+pub use naga::back::glsl::Version as GlslVersion;
+#[cfg(feature = "hlsl")]
+pub use naga::back::hlsl::ShaderModel;
+
+/// The language to which a shader is compiled.
+///
+/// SPIR-V output is binary, while every other target produces UTF-8 encoded source text.
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub enum Target {
+    /// SPIR-V binary, for Vulkan.
+    #[default]
+    Spirv,
+    /// WGSL source, for WebGPU.
+    #[cfg(feature = "wgsl")]
+    Wgsl,
+    /// Metal Shading Language source of the given `(major, minor)` language version.
+    #[cfg(feature = "msl")]
+    Msl { version: (u8, u8) },
+    /// HLSL source for the given shader model, for Direct3D.
+    #[cfg(feature = "hlsl")]
+    Hlsl { shader_model: ShaderModel },
+    /// GLSL source of the given version, e.g. `GlslVersion::new_gles(300)` for WebGL2.
+    ///
+    /// Unlike the other targets, GLSL output contains a single entry point. This is the entry
+    /// point specified via `CompileOptions::entry_point`, or otherwise the first entry point of
+    /// the shader's stage.
+    #[cfg(feature = "glsl")]
+    Glsl { version: GlslVersion },
+}
+
+impl Target {
+    /// Whether or not the target produces source text rather than binary.
+    pub fn is_text(&self) -> bool {
+        !matches!(*self, Target::Spirv)
+    }
+}
+
+/// Write the validated module in the target language specified by the given options.
+pub(crate) fn write(
+    module: &naga::Module,
+    info: &naga::valid::ModuleInfo,
+    stage: ShaderStage,
+    options: &CompileOptions,
+) -> Result<Vec<u8>, NagaError> {
+    let bytes = match options.target {
+        Target::Spirv => {
+            let opts = options.spv_options();
+            let pl_opts = options.spv_pipeline_options(stage);
+            let words = naga::back::spv::write_vec(module, info, &opts, pl_opts.as_ref())?;
+            words
+                .iter()
+                .fold(Vec::with_capacity(words.len() * 4), |mut v, w| {
+                    v.extend_from_slice(&w.to_le_bytes());
+                    v
+                })
+        }
+        #[cfg(feature = "wgsl")]
+        Target::Wgsl => {
+            let flags = naga::back::wgsl::WriterFlags::empty();
+            naga::back::wgsl::write_string(module, info, flags)?.into_bytes()
+        }
+        #[cfg(feature = "msl")]
+        Target::Msl { version } => {
+            let opts = naga::back::msl::Options {
+                lang_version: version,
+                bounds_check_policies: options.bounds_check_policies,
+                ..Default::default()
+            };
+            let pl_opts = naga::back::msl::PipelineOptions::default();
+            let (source, _) = naga::back::msl::write_string(module, info, &opts, &pl_opts)?;
+            source.into_bytes()
+        }
+        #[cfg(feature = "hlsl")]
+        Target::Hlsl { shader_model } => {
+            let opts = naga::back::hlsl::Options {
+                shader_model,
+                ..Default::default()
+            };
+            let mut source = String::new();
+            naga::back::hlsl::Writer::new(&mut source, &opts).write(module, info)?;
+            source.into_bytes()
+        }
+        #[cfg(feature = "glsl")]
+        Target::Glsl { version } => {
+            let opts = naga::back::glsl::Options {
+                version,
+                ..Default::default()
+            };
+            let entry_point = match options.entry_point {
+                Some(ref name) => name.clone(),
+                None => module
+                    .entry_points
+                    .iter()
+                    .find(|ep| ep.stage == stage)
+                    .map(|ep| ep.name.clone())
+                    .unwrap_or_default(),
+            };
+            let pl_opts = naga::back::glsl::PipelineOptions {
+                shader_stage: stage,
+                entry_point,
+                multiview: None,
+            };
+            let mut source = String::new();
+            let policies = options.bounds_check_policies;
+            naga::back::glsl::Writer::new(&mut source, module, info, &opts, &pl_opts, policies)?
+                .write()?;
+            source.into_bytes()
+        }
+    };
+    Ok(bytes)
+}
@@ -0,0 +1,317 @@
+//! A pool of background threads for compiling touched shaders off the caller's thread.
+//!
+//! See `Watch::spawn_compile_workers` and `Watch::try_recv_compiled`.
+
+use crate::cache::CompileCache;
+use crate::{program, CompileError, CompileOptions, Diagnostic, Program};
+use std::collections::HashMap;
+use std::path::{Path, PathBuf};
+use std::sync::{mpsc, Arc, Mutex};
+
+/// A touched shader's path along with the result of compiling it.
+pub type CompiledPath = (PathBuf, Result<Vec<u8>, CompileError>);
+
+/// A request for a worker to compile the shader at `path`.
+struct Job {
+    path: PathBuf,
+    generation: u64,
+    options: CompileOptions,
+    cache: Arc<CompileCache>,
+    /// The registered programs of which the shader is a stage.
+    programs: Vec<Program>,
+}
+
+/// The result of a `Job`.
+struct Compiled {
+    path: PathBuf,
+    generation: u64,
+    result: Result<Vec<u8>, CompileError>,
+    /// The interface mismatches of the job's programs, if the shader compiled.
+    interface: Vec<Diagnostic>,
+}
+
+/// The latest generation requested for each path.
+///
+/// A path's generation is bumped each time it is touched so that stale jobs can be skipped and
+/// stale results discarded.
+type Generations = Arc<Mutex<HashMap<PathBuf, u64>>>;
+
+/// A pool of worker threads compiling shaders from a bounded queue.
+pub(crate) struct CompileWorkers {
+    job_tx: mpsc::SyncSender<Job>,
+    result_rx: Mutex<mpsc::Receiver<Compiled>>,
+    generations: Generations,
+    /// Touched paths waiting for space within the queue, in the order in which they were touched.
+    backlog: Mutex<Vec<PathBuf>>,
+    /// Touched paths whose latest compile has not yet been received, in the order in which they
+    /// were touched.
+    unreceived: Mutex<Vec<PathBuf>>,
+}
+
+impl CompileWorkers {
+    /// Spawn `workers` threads sharing a queue of at most `queue_capacity` jobs.
+    ///
+    /// The threads exit once the `CompileWorkers` is dropped and the queue has drained.
+    pub(crate) fn spawn(workers: usize, queue_capacity: usize) -> Self {
+        let (job_tx, job_rx) = mpsc::sync_channel::<Job>(queue_capacity);
+        let (result_tx, result_rx) = mpsc::channel();
+        let job_rx = Arc::new(Mutex::new(job_rx));
+        let generations = Generations::default();
+        for _ in 0..workers.max(1) {
+            let job_rx = job_rx.clone();
+            let result_tx = result_tx.clone();
+            let generations = generations.clone();
+            std::thread::spawn(move || loop {
+                let job = match job_rx.lock().unwrap().recv() {
+                    Ok(job) => job,
+                    Err(_) => return,
+                };
+                // Skip the job if the path has been touched again since it was queued.
+                if is_stale(&generations, &job.path, job.generation) {
+                    continue;
+                }
+                let result = job.cache.compile_bytes(&job.path, &job.options);
+                let interface = match result {
+                    Ok(_) => {
+                        program::check_stage(&job.programs, &job.path, &job.options, &job.cache)
+                    }
+                    Err(_) => vec![],
+                };
+                let compiled = Compiled {
+                    path: job.path,
+                    generation: job.generation,
+                    result,
+                    interface,
+                };
+                if result_tx.send(compiled).is_err() {
+                    return;
+                }
+            });
+        }
+        CompileWorkers {
+            job_tx,
+            result_rx: Mutex::new(result_rx),
+            generations,
+            backlog: Mutex::new(vec![]),
+            unreceived: Mutex::new(vec![]),
+        }
+    }
+
+    /// Queue the given touched path for compilation.
+    ///
+    /// Any earlier compilation of the same path that has not yet been received is made stale, even
+    /// if the path must wait within the backlog for space in the queue.
+    pub(crate) fn touch(&self, path: PathBuf) {
+        let mut backlog = self.backlog.lock().unwrap();
+        let mut generations = self.generations.lock().unwrap();
+        *generations.entry(path.clone()).or_insert(0) += 1;
+        let mut unreceived = self.unreceived.lock().unwrap();
+        if !unreceived.contains(&path) {
+            unreceived.push(path.clone());
+        }
+        if !backlog.contains(&path) {
+            backlog.push(path);
+        }
+    }
+
+    /// Move as many paths as possible from the backlog into the queue.
+    ///
+    /// Each job also checks the interfaces of those of the given programs of which its shader is
+    /// a stage.
+    pub(crate) fn dispatch(
+        &self,
+        options: &CompileOptions,
+        cache: &Arc<CompileCache>,
+        programs: &[Program],
+    ) {
+        let mut backlog = self.backlog.lock().unwrap();
+        while !backlog.is_empty() {
+            let generation = self.generations.lock().unwrap()[&backlog[0]];
+            let path = &backlog[0];
+            let job = Job {
+                path: path.clone(),
+                generation,
+                options: options.clone(),
+                cache: cache.clone(),
+                programs: programs
+                    .iter()
+                    .filter(|p| p.contains(path))
+                    .cloned()
+                    .collect(),
+            };
+            if self.job_tx.try_send(job).is_err() {
+                break;
+            }
+            backlog.remove(0);
+        }
+    }
+
+    /// Receive the next finished compilation that is not stale, if any, along with the interface
+    /// mismatches of its programs.
+    pub(crate) fn try_recv(&self) -> Option<(CompiledPath, Vec<Diagnostic>)> {
+        let result_rx = self.result_rx.lock().unwrap();
+        while let Ok(compiled) = result_rx.try_recv() {
+            if !is_stale(&self.generations, &compiled.path, compiled.generation) {
+                self.unreceived
+                    .lock()
+                    .unwrap()
+                    .retain(|path| *path != compiled.path);
+                let compiled_path = (compiled.path, compiled.result);
+                return Some((compiled_path, compiled.interface));
+            }
+        }
+        None
+    }
+
+    /// Consume the pool, producing the touched paths whose latest compile has not been received,
+    /// whether they are waiting within the backlog or queue, being compiled, or finished.
+    ///
+    /// The workers finish their current compile before exiting, but its result is discarded.
+    pub(crate) fn into_unreceived(self) -> Vec<PathBuf> {
+        self.unreceived.into_inner().unwrap()
+    }
+}
+
+/// Whether or not a newer job has been queued for the given path.
+fn is_stale(generations: &Generations, path: &Path, generation: u64) -> bool {
+    generations.lock().unwrap().get(path) != Some(&generation)
+}
+
+#[cfg(all(test, unix))]
+mod tests {
+    use super::*;
+    use crate::common::TempDir;
+    use std::time::{Duration, Instant};
+
+    const SHADER: &str = "#version 450\nvoid main() {}\n";
+
+    // A shader whose compilation blocks until the pipe is written to.
+    fn fifo(path: &Path) {
+        let status = std::process::Command::new("mkfifo")
+            .arg(path)
+            .status()
+            .unwrap();
+        assert!(status.success());
+    }
+
+    // Dispatch and receive until `count` results arrive, then check that no more follow.
+    fn recv(
+        workers: &CompileWorkers,
+        cache: &Arc<CompileCache>,
+        count: usize,
+    ) -> Vec<CompiledPath> {
+        let options = CompileOptions::default();
+        let mut compiled = vec![];
+        let start = Instant::now();
+        while start.elapsed() < Duration::from_secs(5) {
+            workers.dispatch(&options, cache, &[]);
+            compiled.extend(workers.try_recv().map(|(compiled, _)| compiled));
+            if compiled.len() == count {
+                std::thread::sleep(Duration::from_millis(100));
+                workers.dispatch(&options, cache, &[]);
+                assert!(workers.try_recv().is_none());
+                break;
+            }
+        }
+        assert_eq!(compiled.len(), count);
+        compiled
+    }
+
+    #[test]
+    fn queue_is_bounded() {
+        let dir = TempDir::new("worker-bounded");
+        let slow = dir.join("slow.frag");
+        fifo(&slow);
+        let cache = Arc::new(CompileCache::new(0));
+        let options = CompileOptions::default();
+        let workers = CompileWorkers::spawn(1, 2);
+        workers.touch(slow.clone());
+        workers.dispatch(&options, &cache, &[]);
+        // Wait for the only worker to block on the slow shader.
+        std::thread::sleep(Duration::from_millis(100));
+        let paths: Vec<_> = (0..5).map(|i| dir.join(format!("{}.frag", i))).collect();
+        for path in &paths {
+            workers.touch(path.clone());
+        }
+        workers.dispatch(&options, &cache, &[]);
+        assert_eq!(*workers.backlog.lock().unwrap(), paths[2..].to_vec());
+
+        std::fs::write(&slow, SHADER).unwrap();
+        let mut compiled: Vec<_> = recv(&workers, &cache, 6).into_iter().map(|c| c.0).collect();
+        compiled.sort();
+        let mut expected = paths;
+        expected.push(slow);
+        assert_eq!(compiled, expected);
+    }
+
+    #[test]
+    fn stale_results_are_dropped_while_the_queue_is_full() {
+        let dir = TempDir::new("worker-stale");
+        let slow = dir.join("slow.frag");
+        fifo(&slow);
+        let cache = Arc::new(CompileCache::new(0));
+        let options = CompileOptions::default();
+        let workers = CompileWorkers::spawn(1, 1);
+        workers.touch(slow.clone());
+        workers.dispatch(&options, &cache, &[]);
+        std::thread::sleep(Duration::from_millis(100));
+        let other = dir.join("other.frag");
+        workers.touch(other.clone());
+        workers.dispatch(&options, &cache, &[]);
+        // The queue is full, so the slow shader waits within the backlog.
+        workers.touch(slow.clone());
+        workers.dispatch(&options, &cache, &[]);
+        assert_eq!(*workers.backlog.lock().unwrap(), vec![slow.clone()]);
+
+        // Complete the stale compile with a broken shader, then replace the pipe with a valid one.
+        std::fs::write(&slow, "#version 450\nvoid main() { oops }\n").unwrap();
+        std::fs::remove_file(&slow).unwrap();
+        std::fs::write(&slow, SHADER).unwrap();
+        std::thread::sleep(Duration::from_millis(100));
+        let mut compiled: Vec<_> =
+            std::iter::from_fn(|| workers.try_recv().map(|(compiled, _)| compiled)).collect();
+        assert!(compiled.iter().all(|c| c.0 != slow));
+        compiled.extend(recv(&workers, &cache, 2 - compiled.len()));
+        let slow_results: Vec<_> = compiled.iter().filter(|c| c.0 == slow).collect();
+        assert_eq!(slow_results.len(), 1);
+        assert!(slow_results[0].1.is_ok());
+    }
+
+    #[test]
+    fn unreceived_paths_are_returned_once_the_pool_is_consumed() {
+        let dir = TempDir::new("worker-unreceived");
+        let slow = dir.join("slow.frag");
+        fifo(&slow);
+        let cache = Arc::new(CompileCache::new(0));
+        let options = CompileOptions::default();
+        let workers = CompileWorkers::spawn(1, 1);
+        workers.touch(slow.clone());
+        workers.dispatch(&options, &cache, &[]);
+        std::thread::sleep(Duration::from_millis(100));
+        let queued = dir.join("queued.frag");
+        let waiting = dir.join("waiting.frag");
+        workers.touch(queued.clone());
+        workers.touch(waiting.clone());
+        workers.dispatch(&options, &cache, &[]);
+        assert_eq!(*workers.backlog.lock().unwrap(), vec![waiting.clone()]);
+
+        // The slow shader is being compiled, one is queued and the other is within the backlog.
+        assert_eq!(
+            workers.into_unreceived(),
+            vec![slow.clone(), queued, waiting]
+        );
+        std::fs::write(&slow, SHADER).unwrap();
+    }
+
+    #[test]
+    fn received_paths_are_not_returned_once_the_pool_is_consumed() {
+        let dir = TempDir::new("worker-received");
+        let path = dir.join("shader.frag");
+        std::fs::write(&path, SHADER).unwrap();
+        let cache = Arc::new(CompileCache::new(0));
+        let workers = CompileWorkers::spawn(1, 1);
+        workers.touch(path.clone());
+        recv(&workers, &cache, 1);
+        assert!(workers.into_unreceived().is_empty());
+    }
+}
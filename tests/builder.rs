@@ -0,0 +1,118 @@
+mod common;
+
+use common::TempDir;
+use std::path::{Path, PathBuf};
+use std::time::Duration;
+
+const SHADER: &str = "#version 450\nvoid main() {}\n";
+
+fn touched(watch: &hotglsl::Watch) -> Vec<PathBuf> {
+    watch.touch_all();
+    let mut paths: Vec<_> = watch.compile_touched().unwrap().map(|(p, _)| p).collect();
+    paths.sort();
+    paths
+}
+
+// A shader directly within the given directory, one directory down and two directories down.
+fn write_nested(dir: &Path) -> Vec<PathBuf> {
+    let shaders = vec![
+        dir.join("a.comp"),
+        dir.join("x/b.comp"),
+        dir.join("x/y/c.comp"),
+    ];
+    std::fs::create_dir_all(dir.join("x/y")).unwrap();
+    for shader in &shaders {
+        std::fs::write(shader, SHADER).unwrap();
+    }
+    shaders
+}
+
+#[test]
+fn max_depth_limits_the_directories_scanned() {
+    let dir = TempDir::new("builder-depth");
+    let shaders = write_nested(&dir);
+    for depth in 0..3 {
+        let watch = hotglsl::WatchBuilder::new()
+            .path(&dir)
+            .max_depth(Some(depth))
+            .build()
+            .unwrap();
+        assert_eq!(touched(&watch), shaders[..depth + 1].to_vec());
+    }
+    let watch = hotglsl::WatchBuilder::new().path(&dir).build().unwrap();
+    assert_eq!(touched(&watch), shaders);
+}
+
+#[test]
+fn max_depth_limits_the_events_yielded() {
+    let dir = TempDir::new("builder-depth-events");
+    let shaders = write_nested(&dir);
+    let watch = hotglsl::WatchBuilder::new()
+        .path(&dir)
+        .max_depth(Some(1))
+        .build()
+        .unwrap();
+    std::fs::write(&shaders[2], SHADER).unwrap();
+    std::thread::sleep(Duration::from_millis(200));
+    assert_eq!(watch.try_next_path().unwrap(), None);
+    std::fs::write(&shaders[1], SHADER).unwrap();
+    std::thread::sleep(Duration::from_millis(200));
+    assert_eq!(watch.try_next_path().unwrap(), Some(shaders[1].clone()));
+}
+
+#[cfg(unix)]
+#[test]
+fn symlinks_are_skipped_unless_followed() {
+    use std::os::unix::fs::symlink;
+
+    let dir = TempDir::new("builder-symlinks");
+    let outside = TempDir::new("builder-symlinks-outside");
+    let shader = dir.join("a.comp");
+    std::fs::write(&shader, SHADER).unwrap();
+    std::fs::write(outside.join("b.comp"), SHADER).unwrap();
+    symlink(&*outside, dir.join("linked")).unwrap();
+    symlink(outside.join("b.comp"), dir.join("alias.comp")).unwrap();
+
+    let watch = hotglsl::WatchBuilder::new()
+        .path(&dir)
+        .follow_symlinks(false)
+        .build()
+        .unwrap();
+    assert_eq!(touched(&watch), vec![shader.clone()]);
+    // Shaders reached via a symlinked directory are ignored, unlike those beside it.
+    std::fs::write(dir.join("linked/c.comp"), SHADER).unwrap();
+    std::thread::sleep(Duration::from_millis(200));
+    assert_eq!(watch.try_next_path().unwrap(), None);
+    std::fs::write(&shader, SHADER).unwrap();
+    std::thread::sleep(Duration::from_millis(200));
+    assert_eq!(watch.try_next_path().unwrap(), Some(shader.clone()));
+
+    let watch = hotglsl::WatchBuilder::new().path(&dir).build().unwrap();
+    let mut expected = vec![
+        shader,
+        dir.join("alias.comp"),
+        dir.join("linked/b.comp"),
+        dir.join("linked/c.comp"),
+    ];
+    expected.sort();
+    assert_eq!(touched(&watch), expected);
+}
+
+#[cfg(unix)]
+#[test]
+fn symlinked_watched_paths_are_followed() {
+    use std::os::unix::fs::symlink;
+
+    let dir = TempDir::new("builder-symlinked-root");
+    let real = dir.join("real");
+    std::fs::create_dir_all(&real).unwrap();
+    std::fs::write(real.join("a.comp"), SHADER).unwrap();
+    let link = dir.join("link");
+    symlink(&real, &link).unwrap();
+    let watch = hotglsl::WatchBuilder::new()
+        .path(&link)
+        .follow_symlinks(false)
+        .build()
+        .unwrap();
+    assert_eq!(touched(&watch), vec![link.join("a.comp")]);
+}
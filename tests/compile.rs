@@ -0,0 +1,126 @@
+mod common;
+
+use common::TempDir;
+use hotglsl::CompileError;
+
+#[test]
+fn compiling_a_missing_file_is_an_io_error() {
+    let dir = TempDir::new("compile-missing");
+    let path = dir.join("missing.frag");
+    match hotglsl::compile(&path) {
+        Err(CompileError::Io { .. }) => (),
+        other => panic!("expected an I/O error, got {:?}", other),
+    }
+}
+
+#[test]
+fn a_missing_include_is_a_preprocess_error() {
+    let dir = TempDir::new("compile-missing-include");
+    let path = dir.join("shader.frag");
+    std::fs::write(&path, "#version 450\n#include \"missing.glsl\"\n").unwrap();
+    match hotglsl::compile(&path) {
+        Err(CompileError::Preprocess { .. }) => (),
+        other => panic!("expected a preprocess error, got {:?}", other),
+    }
+}
+
+#[test]
+fn includes_within_uncompiled_blocks_are_ignored() {
+    let dir = TempDir::new("compile-conditional-include");
+    let path = dir.join("shader.frag");
+    let source = "#version 450
+#if 0
+#include \"missing.glsl\"
+#endif
+#ifdef FANCY
+#include \"fancy.glsl\"
+#endif
+/*
+#include \"missing.glsl\"
+*/
+layout(location = 0) out vec4 color;
+void main() {
+    color = vec4(1.0);
+}
+";
+    std::fs::write(&path, source).unwrap();
+    hotglsl::compile(&path).unwrap();
+
+    // Once `FANCY` is defined the missing include is reported as such.
+    let options = hotglsl::CompileOptions::default().define("FANCY", "1");
+    match hotglsl::compile_with_options(&path, &options) {
+        Err(CompileError::Preprocess { .. }) => (),
+        other => panic!("expected a preprocess error, got {:?}", other),
+    }
+}
+
+#[test]
+fn includes_within_blocks_naga_compiles_are_reported() {
+    // The condition is left to naga, which compiles the block.
+    let source = "#version 450
+#define LEVEL 2
+#if LEVEL > 1
+#include \"missing.glsl\"
+#endif
+void main() {}
+";
+    let options = hotglsl::CompileOptions::default();
+    match hotglsl::compile_str_with_options(source, hotglsl::ShaderStage::Fragment, &options) {
+        Err(CompileError::Preprocess {
+            err: hotglsl::PreprocessError::NotFound { line: 4, .. },
+        }) => (),
+        other => panic!("expected a missing include, got {:?}", other),
+    }
+}
+
+const PERMUTED: &str = "#version 450
+layout(location = 0) out vec4 color;
+void main() {
+#if BRIGHT == 1
+    color = vec4(1.0);
+#else
+    color = vec4(0.5);
+#endif
+#if BROKEN == 1
+    oops;
+#endif
+}
+";
+
+#[test]
+fn each_permutation_is_compiled_with_its_defines() {
+    let dir = TempDir::new("compile-permutations");
+    let path = dir.join("shader.frag");
+    std::fs::write(&path, PERMUTED).unwrap();
+    let options = hotglsl::CompileOptions::default();
+    let permutations = hotglsl::Permutations::new().axis("BRIGHT", 0..2);
+    let compiled = hotglsl::compile_permutations(&path, &options, &permutations).unwrap();
+    assert_eq!(compiled.len(), 2);
+    let outputs: Vec<_> = compiled.values().collect();
+    assert_ne!(outputs[0], outputs[1]);
+    for (key, bytes) in &compiled {
+        let options = options.clone().defines(key.defines().iter());
+        assert_eq!(
+            *bytes,
+            hotglsl::compile_with_options(&path, &options).unwrap()
+        );
+    }
+}
+
+#[test]
+fn a_broken_permutation_is_reported_with_its_key() {
+    let dir = TempDir::new("compile-broken-permutation");
+    let path = dir.join("shader.frag");
+    std::fs::write(&path, PERMUTED).unwrap();
+    let options = hotglsl::CompileOptions::default();
+    let permutations = hotglsl::Permutations::new()
+        .axis("BRIGHT", 0..1)
+        .axis("BROKEN", 0..2);
+    match hotglsl::compile_permutations(&path, &options, &permutations) {
+        Err(CompileError::Permutation { key, err }) => {
+            assert_eq!(key.to_string(), "BRIGHT=0,BROKEN=1");
+            assert!(matches!(*err, CompileError::Naga { .. }));
+        }
+        other => panic!("expected a broken permutation, got {:?}", other),
+    }
+}
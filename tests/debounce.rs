@@ -0,0 +1,27 @@
+mod common;
+
+use common::TempDir;
+use hotglsl::ManualClock;
+use std::time::Duration;
+
+const SHADER: &str = "#version 450\nvoid main() {}\n";
+
+#[test]
+fn watch_yields_touched_shader_once_window_elapses() {
+    let dir = TempDir::new("debounce-window");
+    let window = Duration::from_secs(60);
+    let clock = ManualClock::new();
+    let mut watch = hotglsl::watch(&dir).unwrap();
+    watch.set_debounce_window(window);
+    watch.set_clock(clock.clone());
+    let shader = dir.join("shader.frag");
+    for ix in 0..3 {
+        std::fs::write(&shader, format!("{}// {}\n", SHADER, ix)).unwrap();
+    }
+    // Give notify a chance to deliver the events of the burst.
+    std::thread::sleep(Duration::from_millis(200));
+    assert_eq!(watch.try_next_path().unwrap(), None);
+    clock.advance(window);
+    assert_eq!(watch.try_next_path().unwrap(), Some(shader));
+    assert_eq!(watch.try_next_path().unwrap(), None);
+}
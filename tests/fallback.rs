@@ -0,0 +1,56 @@
+mod common;
+
+use common::TempDir;
+
+const GOOD: &str = "#version 450
+layout(location = 0) out vec4 color;
+void main() {
+    color = vec4(1.0);
+}
+";
+
+const BROKEN: &str = "#version 450
+void main() { oops }
+";
+
+#[test]
+fn broken_shaders_fall_back_to_their_last_good_output() {
+    let dir = TempDir::new("fallback");
+    let path = dir.join("shader.frag");
+    std::fs::write(&path, GOOD).unwrap();
+    let watch = hotglsl::watch(&dir).unwrap();
+    watch.touch_all();
+    let outcomes: Vec<_> = watch.compile_touched_with_fallback().unwrap().collect();
+    assert_eq!(outcomes.len(), 1);
+    let good = outcomes[0].result.as_ref().unwrap().clone();
+    assert_eq!(outcomes[0].bytes(), Some(&good[..]));
+    assert!(outcomes[0].last_good.is_none());
+
+    std::fs::write(&path, BROKEN).unwrap();
+    watch.touch_all();
+    let outcomes: Vec<_> = watch.compile_touched_with_fallback().unwrap().collect();
+    assert!(outcomes[0].error().is_some());
+    assert_eq!(outcomes[0].bytes(), Some(&good[..]));
+    assert_eq!(watch.last_good(&path), Some(good));
+}
+
+#[test]
+fn reflected_compiles_are_retained_as_last_good_output() {
+    let dir = TempDir::new("fallback-reflected");
+    let path = dir.join("shader.frag");
+    std::fs::write(&path, GOOD).unwrap();
+    let watch = hotglsl::watch(&dir).unwrap();
+    watch.touch_all();
+    let (_, result) = watch.compile_touched_reflected().unwrap().next().unwrap();
+    let good = result.unwrap().bytes;
+    assert_eq!(watch.last_good(&path), Some(good.clone()));
+
+    std::fs::write(&path, BROKEN).unwrap();
+    watch.touch_all();
+    let outcome = watch
+        .compile_touched_with_fallback()
+        .unwrap()
+        .next()
+        .unwrap();
+    assert_eq!(outcome.bytes(), Some(&good[..]));
+}
@@ -0,0 +1,66 @@
+//! Compilation of shaders written in languages other than GLSL.
+#![cfg(any(feature = "wgsl-in", feature = "spv-in"))]
+
+mod common;
+
+use common::TempDir;
+use hotglsl::{CompileError, CompileOptions, NagaError, ShaderStage};
+
+#[cfg(feature = "wgsl-in")]
+#[test]
+fn wgsl_is_compiled() {
+    let dir = TempDir::new("inputs-wgsl");
+    let path = dir.join("shader.wgsl");
+    let source = "@fragment\nfn main() -> @location(0) vec4<f32> {\n    return vec4(1.0);\n}\n";
+    std::fs::write(&path, source).unwrap();
+    let shader = hotglsl::compile_reflected(&path, &CompileOptions::default()).unwrap();
+    assert_eq!(shader.reflection.stage, Some(ShaderStage::Fragment));
+    assert_eq!(shader.bytes[..4], 0x0723_0203u32.to_le_bytes());
+}
+
+#[cfg(feature = "wgsl-in")]
+#[test]
+fn wgsl_parse_errors_are_located() {
+    let dir = TempDir::new("inputs-wgsl-error");
+    let path = dir.join("shader.wgsl");
+    std::fs::write(&path, "@fragment\nfn main() {\n    let x = ;\n}\n").unwrap();
+    let err = hotglsl::compile(&path).unwrap_err();
+    match err {
+        CompileError::Naga { ref err, .. } => {
+            assert!(matches!(**err, NagaError::WgslFront { .. }));
+        }
+        ref other => panic!("expected a naga error, got {:?}", other),
+    }
+    let diagnostics = err.diagnostics();
+    let range = diagnostics[0].primary_range().unwrap();
+    assert_eq!(range.path.as_deref(), Some(path.as_path()));
+    assert_eq!(range.start.line, 3);
+}
+
+#[cfg(feature = "spv-in")]
+#[test]
+fn spirv_is_validated_and_reemitted() {
+    let dir = TempDir::new("inputs-spv");
+    let glsl =
+        std::path::Path::new(env!("CARGO_MANIFEST_DIR")).join("examples/shaders/shader.frag");
+    let path = dir.join("shader.spv");
+    std::fs::write(&path, hotglsl::compile(&glsl).unwrap()).unwrap();
+    let shader = hotglsl::compile_reflected(&path, &CompileOptions::default()).unwrap();
+    assert_eq!(shader.reflection.stage, Some(ShaderStage::Fragment));
+    assert_eq!(shader.reflection.inputs.len(), 1);
+}
+
+#[cfg(feature = "spv-in")]
+#[test]
+fn invalid_spirv_is_a_naga_error() {
+    let dir = TempDir::new("inputs-spv-error");
+    let path = dir.join("shader.spv");
+    std::fs::write(&path, [0u8; 16]).unwrap();
+    match hotglsl::compile(&path) {
+        Err(CompileError::Naga { err, diagnostics }) => {
+            assert!(matches!(*err, NagaError::SpvFront { .. }));
+            assert_eq!(diagnostics[0].path.as_deref(), Some(path.as_path()));
+        }
+        other => panic!("expected a naga error, got {:?}", other),
+    }
+}
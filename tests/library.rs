@@ -0,0 +1,111 @@
+mod common;
+
+use common::TempDir;
+use hotglsl::ShaderLibrary;
+use std::path::{Path, PathBuf};
+use std::time::Duration;
+
+const SHADER: &str = "#version 450\nvoid main() {}\n";
+const MODIFIED: &str =
+    "#version 450\nlayout(location = 0) out vec4 c;\nvoid main() { c = vec4(1.0); }\n";
+const BROKEN: &str = "#version 450\nvoid main() { oops }\n";
+
+fn library(dir: &Path) -> ShaderLibrary {
+    ShaderLibrary::new(hotglsl::watch(dir).unwrap())
+}
+
+// Give notify a moment to deliver the events.
+fn settle() {
+    std::thread::sleep(Duration::from_millis(200));
+}
+
+#[test]
+fn generations_only_increase() {
+    let dir = TempDir::new("library-generations");
+    let shader = dir.join("shader.frag");
+    std::fs::write(&shader, SHADER).unwrap();
+    let mut library = library(&dir);
+    assert_eq!(library.update().unwrap(), vec![shader.clone()]);
+    let first = library.get(&shader).unwrap().generation();
+    assert_eq!(first, library.generation());
+
+    std::fs::write(&shader, MODIFIED).unwrap();
+    settle();
+    assert_eq!(library.update().unwrap(), vec![shader.clone()]);
+    let second = library.get(&shader).unwrap().generation();
+    assert!(second > first);
+
+    std::fs::remove_file(&shader).unwrap();
+    settle();
+    library.update().unwrap();
+    std::fs::write(&shader, SHADER).unwrap();
+    settle();
+    library.update().unwrap();
+    let third = library.get(&shader).unwrap().generation();
+    assert!(third > second);
+    assert_eq!(third, library.generation());
+}
+
+#[test]
+fn error_changes_are_reported_without_advancing_the_generation() {
+    let dir = TempDir::new("library-errors");
+    let shader = dir.join("shader.frag");
+    std::fs::write(&shader, SHADER).unwrap();
+    let mut library = library(&dir);
+    library.update().unwrap();
+    let entry = library.get(&shader).unwrap();
+    let (generation, bytes) = (entry.generation(), entry.bytes().unwrap().to_vec());
+
+    std::fs::write(&shader, BROKEN).unwrap();
+    settle();
+    assert_eq!(library.update().unwrap(), vec![shader.clone()]);
+    let entry = library.get(&shader).unwrap();
+    assert!(entry.error().is_some());
+    assert_eq!(entry.bytes(), Some(&bytes[..]));
+    assert_eq!(entry.generation(), generation);
+
+    // Restoring the same source clears the error, although the output is unchanged.
+    std::fs::write(&shader, SHADER).unwrap();
+    settle();
+    assert_eq!(library.update().unwrap(), vec![shader.clone()]);
+    let entry = library.get(&shader).unwrap();
+    assert!(entry.error().is_none());
+    assert_eq!(entry.generation(), generation);
+    assert_eq!(library.update().unwrap(), Vec::<PathBuf>::new());
+}
+
+#[test]
+fn removed_shaders_are_forgotten() {
+    let dir = TempDir::new("library-removal");
+    let shader = dir.join("shader.frag");
+    std::fs::write(&shader, SHADER).unwrap();
+    let mut library = library(&dir);
+    library.update().unwrap();
+    let generation = library.generation();
+
+    std::fs::remove_file(&shader).unwrap();
+    settle();
+    assert_eq!(library.update().unwrap(), vec![shader.clone()]);
+    assert!(library.get(&shader).is_none());
+    assert_eq!(library.generation(), generation);
+}
+
+#[test]
+fn renamed_shaders_move_to_their_new_path() {
+    let dir = TempDir::new("library-rename");
+    let from = dir.join("a.frag");
+    let to = dir.join("b.frag");
+    std::fs::write(&from, SHADER).unwrap();
+    let mut library = library(&dir);
+    library.set_name("shader", &to);
+    library.update().unwrap();
+    let generation = library.get(&from).unwrap().generation();
+
+    std::fs::rename(&from, &to).unwrap();
+    settle();
+    assert_eq!(library.update().unwrap(), vec![from.clone(), to.clone()]);
+    assert!(library.get(&from).is_none());
+    let entry = library.get_named("shader").unwrap();
+    assert!(entry.bytes().is_some());
+    assert!(entry.generation() > generation);
+}
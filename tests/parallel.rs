@@ -0,0 +1,45 @@
+#![cfg(feature = "rayon")]
+
+mod common;
+
+use common::TempDir;
+use std::path::PathBuf;
+
+#[test]
+fn parallel_results_are_sorted_by_path() {
+    let dir = TempDir::new("parallel");
+    let nested = dir.join("nested");
+    std::fs::create_dir_all(&nested).unwrap();
+    let mut paths: Vec<PathBuf> = vec![];
+    for i in (0..16).rev() {
+        let parent = if i % 2 == 0 { &*dir } else { &*nested };
+        let path = parent.join(format!("{:02}.frag", i));
+        let source = format!(
+            "#version 450\nlayout(location = 0) out vec4 c;\nvoid main() {{ c = vec4({}.0); }}\n",
+            i
+        );
+        std::fs::write(&path, source).unwrap();
+        paths.push(path);
+    }
+    std::fs::write(
+        dir.join("broken.frag"),
+        "#version 450\nvoid main() { oops }\n",
+    )
+    .unwrap();
+    paths.push(dir.join("broken.frag"));
+    paths.sort();
+
+    let watch = hotglsl::watch(&dir).unwrap();
+    watch.touch_all();
+    let compiled = watch.compile_touched_parallel().unwrap();
+    let compiled_paths: Vec<_> = compiled.iter().map(|(path, _)| path.clone()).collect();
+    assert_eq!(compiled_paths, paths);
+    // Each result matches compiling the shader on its own.
+    for (path, result) in compiled {
+        match hotglsl::compile(&path) {
+            Ok(bytes) => assert_eq!(result.unwrap(), bytes),
+            Err(_) => assert!(result.is_err()),
+        }
+    }
+    assert!(watch.compile_touched_parallel().unwrap().is_empty());
+}
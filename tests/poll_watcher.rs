@@ -0,0 +1,109 @@
+mod common;
+
+use common::TempDir;
+use hotglsl::{Backend, ShaderEvent, WatchBuilder};
+use std::path::{Path, PathBuf};
+use std::time::{Duration, Instant};
+
+const SHADER: &str = "#version 450\nvoid main() {}\n";
+
+const POLL: Backend = Backend::Poll {
+    interval: Duration::from_millis(20),
+    compare_contents: true,
+};
+
+/// Call `f` until it produces a value or a few seconds have passed.
+fn wait_for<T>(mut f: impl FnMut() -> Option<T>) -> Option<T> {
+    let start = Instant::now();
+    while start.elapsed() < Duration::from_secs(5) {
+        if let Some(t) = f() {
+            return Some(t);
+        }
+        std::thread::sleep(Duration::from_millis(10));
+    }
+    None
+}
+
+fn next_path(watch: &hotglsl::Watch) -> Option<PathBuf> {
+    wait_for(|| watch.try_next_path().unwrap())
+}
+
+fn next_removal(watch: &hotglsl::Watch, path: &Path) -> bool {
+    let removed = ShaderEvent::Removed(path.to_path_buf());
+    wait_for(|| {
+        watch
+            .try_next_shader_event()
+            .unwrap()
+            .filter(|e| *e == removed)
+    })
+    .is_some()
+}
+
+#[test]
+fn poll_backend_is_used_when_selected() {
+    let dir = TempDir::new("poll-selected");
+    let watch = WatchBuilder::new()
+        .path(&dir)
+        .backend(POLL)
+        .build()
+        .unwrap();
+    assert_eq!(watch.backend(), POLL);
+}
+
+#[test]
+fn poll_backend_reports_created_shader() {
+    let dir = TempDir::new("poll-created");
+    let watch = WatchBuilder::new()
+        .path(&dir)
+        .backend(POLL)
+        .build()
+        .unwrap();
+    let shader = dir.join("created.frag");
+    std::fs::write(&shader, SHADER).unwrap();
+    assert_eq!(next_path(&watch), Some(shader));
+}
+
+#[test]
+fn poll_backend_reports_modified_and_removed_shader() {
+    let dir = TempDir::new("poll-modified");
+    let shader = dir.join("modified.vert");
+    std::fs::write(&shader, SHADER).unwrap();
+    let watch = WatchBuilder::new()
+        .path(&dir)
+        .backend(POLL)
+        .build()
+        .unwrap();
+    // Give the watcher a chance to complete its initial scan.
+    std::thread::sleep(Duration::from_millis(100));
+    std::fs::write(&shader, format!("{}\n", SHADER)).unwrap();
+    assert_eq!(next_path(&watch), Some(shader.clone()));
+    std::fs::remove_file(&shader).unwrap();
+    assert!(next_removal(&watch, &shader));
+}
+
+#[test]
+fn poll_backend_ignores_non_shader_files() {
+    let dir = TempDir::new("poll-ignored");
+    let watch = WatchBuilder::new()
+        .path(&dir)
+        .backend(POLL)
+        .build()
+        .unwrap();
+    std::fs::write(dir.join("notes.txt"), "not a shader").unwrap();
+    let shader = dir.join("shader.comp");
+    std::fs::write(&shader, SHADER).unwrap();
+    assert_eq!(next_path(&watch), Some(shader));
+    std::thread::sleep(Duration::from_millis(100));
+    assert_eq!(watch.try_next_path().unwrap(), None);
+}
+
+#[test]
+fn missing_path_is_an_error_despite_poll_fallback() {
+    let dir = TempDir::new("poll-missing");
+    let interval = Some(Duration::from_millis(20));
+    let result = WatchBuilder::new()
+        .path(dir.join("missing"))
+        .poll_fallback(interval)
+        .build();
+    assert!(result.is_err());
+}
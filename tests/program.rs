@@ -0,0 +1,116 @@
+mod common;
+
+use common::TempDir;
+use hotglsl::{CompileError, Program};
+use std::time::{Duration, Instant};
+
+const VERTEX: &str = "#version 450
+layout(location = 0) out vec3 color;
+void main() {
+    color = vec3(1.0);
+    gl_Position = vec4(0.0);
+}
+";
+
+const FRAGMENT: &str = "#version 450
+layout(location = 0) in vec2 color;
+layout(location = 0) out vec4 out_color;
+void main() {
+    out_color = vec4(color, 0.0, 1.0);
+}
+";
+
+fn write_program(dir: &std::path::Path, fragment: &str) -> Program {
+    let program = Program::new(dir.join("shader.vert"), dir.join("shader.frag"));
+    std::fs::write(&program.vertex, VERTEX).unwrap();
+    std::fs::write(&program.fragment, fragment).unwrap();
+    program
+}
+
+#[test]
+fn mismatched_types_are_reported() {
+    let dir = TempDir::new("program-mismatch");
+    let program = write_program(&dir, FRAGMENT);
+    let options = hotglsl::CompileOptions::default();
+    let vertex = hotglsl::compile_reflected(&program.vertex, &options).unwrap();
+    let fragment = hotglsl::compile_reflected(&program.fragment, &options).unwrap();
+    let diagnostics = hotglsl::check_interface(
+        &vertex.reflection,
+        &program.vertex,
+        &fragment.reflection,
+        &program.fragment,
+    );
+    assert_eq!(diagnostics.len(), 1);
+    assert!(diagnostics[0].message.contains("does not match"));
+    match hotglsl::compile_program(&program, &options) {
+        Err(CompileError::InterfaceMismatch { .. }) => (),
+        other => panic!("expected an interface mismatch, got {:?}", other),
+    }
+}
+
+#[test]
+fn matching_interfaces_are_accepted() {
+    let dir = TempDir::new("program-match");
+    let fragment = FRAGMENT
+        .replace("in vec2", "in vec3")
+        .replace("vec4(color, 0.0, 1.0)", "vec4(color, 1.0)");
+    let program = write_program(&dir, &fragment);
+    let options = hotglsl::CompileOptions::default();
+    hotglsl::compile_program(&program, &options).unwrap();
+}
+
+#[test]
+fn compile_touched_reports_mismatches_alongside_output() {
+    let dir = TempDir::new("program-touched");
+    let program = write_program(&dir, FRAGMENT);
+    let mut watch = hotglsl::watch(&dir).unwrap();
+    watch.add_program(program.clone());
+    watch.touch_all();
+    let compiled: Vec<_> = watch.compile_touched().unwrap().collect();
+    assert_eq!(compiled.len(), 2);
+    for (path, result) in compiled {
+        assert!(result.is_ok());
+        assert_eq!(watch.last_good(&path), result.ok());
+        assert_eq!(watch.interface_diagnostics(&path).len(), 1);
+    }
+    // Reflecting the sibling stage for the check is not counted as a compile.
+    let stats = watch.cache_stats();
+    assert_eq!((stats.hits, stats.misses), (0, 2));
+
+    // Fixing the fragment shader clears its mismatch.
+    let fixed = FRAGMENT
+        .replace("in vec2", "in vec3")
+        .replace("vec4(color, 0.0, 1.0)", "vec4(color, 1.0)");
+    std::fs::write(&program.fragment, fixed).unwrap();
+    watch.touch_all();
+    let outcomes: Vec<_> = watch.compile_touched_with_fallback().unwrap().collect();
+    assert_eq!(outcomes.len(), 2);
+    for outcome in outcomes {
+        assert!(outcome.result.is_ok());
+        assert!(outcome.interface.is_empty());
+        assert!(watch.interface_diagnostics(&outcome.path).is_empty());
+    }
+}
+
+#[test]
+fn compile_workers_check_registered_programs() {
+    let dir = TempDir::new("program-workers");
+    let program = write_program(&dir, FRAGMENT);
+    let mut watch = hotglsl::watch(&dir).unwrap();
+    watch.add_program(program.clone());
+    watch.spawn_compile_workers(2, 4);
+    watch.touch_all();
+    let mut compiled = vec![];
+    let start = Instant::now();
+    while compiled.len() < 2 && start.elapsed() < Duration::from_secs(5) {
+        compiled.extend(watch.try_recv_compiled().unwrap());
+        std::thread::sleep(Duration::from_millis(10));
+    }
+    assert_eq!(compiled.len(), 2);
+    for (path, result) in compiled {
+        assert!(result.is_ok());
+        assert_eq!(watch.interface_diagnostics(&path).len(), 1);
+    }
+    assert!(watch.remove_program(&program));
+    assert!(watch.interface_diagnostics(&program.vertex).is_empty());
+}
@@ -0,0 +1,57 @@
+mod common;
+
+use common::TempDir;
+use std::path::{Path, PathBuf};
+
+const SHADER: &str = "#version 450\nvoid main() {}\n";
+
+// Two shaders, one of them nested, alongside a file that is not a shader.
+fn write_shaders(dir: &Path) -> Vec<PathBuf> {
+    let nested = dir.join("nested");
+    std::fs::create_dir_all(&nested).unwrap();
+    let shaders = vec![dir.join("a.comp"), nested.join("b.comp")];
+    for shader in &shaders {
+        std::fs::write(shader, SHADER).unwrap();
+    }
+    std::fs::write(dir.join("notes.txt"), "not a shader").unwrap();
+    shaders
+}
+
+fn compile_touched(watch: &hotglsl::Watch) -> Vec<PathBuf> {
+    let mut paths: Vec<_> = watch
+        .compile_touched()
+        .unwrap()
+        .map(|(path, result)| {
+            assert!(result.is_ok());
+            path
+        })
+        .collect();
+    paths.sort();
+    paths
+}
+
+#[test]
+fn touch_all_touches_every_shader() {
+    let dir = TempDir::new("scan-touch-all");
+    let shaders = write_shaders(&dir);
+    let watch = hotglsl::watch(&dir).unwrap();
+    assert!(compile_touched(&watch).is_empty());
+    assert_eq!(watch.touch_all(), 2);
+    assert_eq!(compile_touched(&watch), shaders);
+    assert!(compile_touched(&watch).is_empty());
+}
+
+#[test]
+fn initial_scan_touches_every_existing_shader() {
+    let dir = TempDir::new("scan-initial");
+    let shaders = write_shaders(&dir);
+    let watch = hotglsl::WatchBuilder::new()
+        .path(&dir)
+        .initial_scan(true)
+        .build()
+        .unwrap();
+    assert_eq!(compile_touched(&watch), shaders);
+
+    let watch = hotglsl::WatchBuilder::new().path(&dir).build().unwrap();
+    assert!(compile_touched(&watch).is_empty());
+}
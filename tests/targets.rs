@@ -0,0 +1,63 @@
+use hotglsl::{CompileError, CompileOptions, Target};
+use std::path::Path;
+
+const SHADER: &str = concat!(env!("CARGO_MANIFEST_DIR"), "/examples/shaders/shader.frag");
+
+#[cfg(any(feature = "wgsl", feature = "msl", feature = "hlsl", feature = "glsl"))]
+fn compile(target: Target) -> String {
+    let options = CompileOptions::default().target(target);
+    let bytes = hotglsl::compile_with_options(Path::new(SHADER), &options).unwrap();
+    String::from_utf8(bytes).unwrap()
+}
+
+#[test]
+fn spirv_output_is_binary() {
+    let bytes = hotglsl::compile(Path::new(SHADER)).unwrap();
+    assert_eq!(bytes[..4], 0x0723_0203u32.to_le_bytes());
+    assert!(!Target::Spirv.is_text());
+}
+
+#[test]
+fn errors_do_not_name_a_target() {
+    let options = CompileOptions::default();
+    let source = "#version 450\nvoid main() { oops }\n";
+    let err = hotglsl::compile_str_with_options(source, hotglsl::ShaderStage::Fragment, &options)
+        .unwrap_err();
+    assert!(matches!(err, CompileError::Naga { .. }));
+    assert!(err
+        .to_string()
+        .starts_with("failed to compile the shader: "));
+}
+
+#[cfg(feature = "wgsl")]
+#[test]
+fn wgsl_output() {
+    let source = compile(Target::Wgsl);
+    assert!(source.contains("@fragment"));
+    assert!(source.contains("@location(0)"));
+}
+
+#[cfg(feature = "msl")]
+#[test]
+fn msl_output() {
+    let source = compile(Target::Msl { version: (2, 0) });
+    assert!(source.contains("#include <metal_stdlib>"));
+    assert!(source.contains("fragment "));
+}
+
+#[cfg(feature = "hlsl")]
+#[test]
+fn hlsl_output() {
+    let shader_model = hotglsl::ShaderModel::V5_1;
+    let source = compile(Target::Hlsl { shader_model });
+    assert!(source.contains("SV_Target0"));
+}
+
+#[cfg(feature = "glsl")]
+#[test]
+fn glsl_output() {
+    let version = hotglsl::GlslVersion::new_gles(300);
+    let source = compile(Target::Glsl { version });
+    assert!(source.starts_with("#version 300 es"));
+    assert!(source.contains("void main()"));
+}
@@ -0,0 +1,81 @@
+mod common;
+
+use common::TempDir;
+
+#[test]
+fn removing_a_deleted_path_succeeds() {
+    let root = TempDir::new("remove-deleted");
+    let project = root.join("project");
+    std::fs::create_dir_all(&project).unwrap();
+    let mut watch = hotglsl::watch(&root).unwrap();
+    watch.add_path(&project).unwrap();
+    std::fs::remove_dir_all(&project).unwrap();
+    assert!(watch.remove_path(&project).unwrap());
+    assert_eq!(watch.watched_paths(), &[root.to_path_buf()][..]);
+    assert!(!watch.remove_path(&project).unwrap());
+}
+
+#[test]
+fn removing_an_unnested_deleted_path_succeeds() {
+    let a = TempDir::new("remove-unnested-a");
+    let b = TempDir::new("remove-unnested-b");
+    let mut watch = hotglsl::watch_paths([&*a, &*b]).unwrap();
+    std::fs::remove_dir_all(&b).unwrap();
+    assert!(watch.remove_path(&b).unwrap());
+    assert_eq!(watch.watched_paths(), &[a.to_path_buf()][..]);
+}
+
+#[test]
+fn removing_a_parent_keeps_nested_paths_watched() {
+    let root = TempDir::new("remove-parent");
+    let nested = root.join("nested");
+    std::fs::create_dir_all(&nested).unwrap();
+    let mut watch = hotglsl::watch_paths([&*root, &*nested]).unwrap();
+    assert!(watch.remove_path(&root).unwrap());
+    let shader = nested.join("shader.frag");
+    std::fs::write(&shader, "#version 450\nvoid main() {}\n").unwrap();
+    std::thread::sleep(std::time::Duration::from_millis(200));
+    assert_eq!(watch.try_next_path().unwrap(), Some(shader));
+}
+
+#[test]
+fn dropping_a_parent_include_dir_keeps_nested_paths_watched() {
+    let root = TempDir::new("drop-parent-include");
+    let shaders = root.join("shaders");
+    std::fs::create_dir_all(&shaders).unwrap();
+    let options = hotglsl::CompileOptions::default().include_dir(&root);
+    let mut watch = hotglsl::watch_paths_with_options(&[&shaders], options).unwrap();
+    watch
+        .set_compile_options(hotglsl::CompileOptions::default())
+        .unwrap();
+    let shader = shaders.join("shader.frag");
+    std::fs::write(&shader, "#version 450\nvoid main() {}\n").unwrap();
+    std::thread::sleep(std::time::Duration::from_millis(200));
+    assert_eq!(watch.try_next_path().unwrap(), Some(shader));
+}
+
+#[test]
+fn failing_to_set_compile_options_leaves_watch_unchanged() {
+    let root = TempDir::new("failed-options");
+    let shaders = root.join("shaders");
+    let include = root.join("include");
+    std::fs::create_dir_all(&shaders).unwrap();
+    std::fs::create_dir_all(&include).unwrap();
+    let options = hotglsl::CompileOptions::default().include_dir(&include);
+    let mut watch = hotglsl::watch_paths_with_options(&[&shaders], options).unwrap();
+    let missing = hotglsl::CompileOptions::default().include_dir(root.join("missing"));
+    assert!(watch.set_compile_options(missing).is_err());
+    assert_eq!(watch.include_dirs(), &[include.clone()][..]);
+    let shader = shaders.join("shader.frag");
+    std::fs::write(
+        &shader,
+        "#version 450\n#include \"common.glsl\"\nvoid main() {}\n",
+    )
+    .unwrap();
+    std::fs::write(include.join("common.glsl"), "// common\n").unwrap();
+    std::thread::sleep(std::time::Duration::from_millis(200));
+    assert!(watch.paths_touched().unwrap().contains(&shader));
+    std::fs::write(include.join("common.glsl"), "// changed\n").unwrap();
+    std::thread::sleep(std::time::Duration::from_millis(200));
+    assert!(watch.paths_touched().unwrap().contains(&shader));
+}
@@ -0,0 +1,27 @@
+mod common;
+
+use common::TempDir;
+use std::path::PathBuf;
+
+const SHADER: &str = "#version 450\nvoid main() {}\n";
+
+#[test]
+fn stopping_workers_touches_unreceived_shaders_again() {
+    let dir = TempDir::new("workers-stop");
+    let mut paths: Vec<PathBuf> = (0..4).map(|i| dir.join(format!("{}.frag", i))).collect();
+    for path in &paths {
+        std::fs::write(path, SHADER).unwrap();
+    }
+    let mut watch = hotglsl::watch(&dir).unwrap();
+    watch.spawn_compile_workers(1, 1);
+    watch.touch_all();
+    // Hand the touched shaders to the workers, then replace and stop them before receiving.
+    let mut received: Vec<_> = watch.try_recv_compiled().unwrap().into_iter().collect();
+    watch.spawn_compile_workers(1, 1);
+    watch.stop_compile_workers();
+    received.extend(watch.compile_touched().unwrap());
+    let mut received: Vec<_> = received.into_iter().map(|(path, _)| path).collect();
+    received.sort();
+    paths.sort();
+    assert_eq!(received, paths);
+}